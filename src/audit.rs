@@ -0,0 +1,133 @@
+//! A record of every notification sent and who it mentioned, kept apart from the general log so
+//! it's easy to find out why someone was (or wasn't) pinged.
+
+use crate::notify::NotifyContent;
+use anyhow::Result;
+use serde_json::json;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Once the audit log is this big it's moved aside, keeping only the one before it
+const MAX_AUDIT_LOG_SIZE: u64 = 10 * 1024 * 1024;
+
+/// Append a line for each notification to the audit log, along with whether sending it worked
+pub fn record(path: &Path, contents: &[NotifyContent], result: &Result<()>) -> Result<()> {
+    rotate_if_full(path)?;
+
+    let time = SystemTime::now();
+    let lines = contents
+        .iter()
+        .map(|content| format_line(content, result, time) + "\n")
+        .collect::<String>();
+
+    // Lines are written in one go, so they can't be interleaved with another writer's
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.write_all(lines.as_bytes())?;
+
+    Ok(())
+}
+
+fn rotate_if_full(path: &Path) -> Result<()> {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return Ok(());
+    };
+
+    if metadata.len() >= MAX_AUDIT_LOG_SIZE {
+        std::fs::rename(path, rotated_path(path))?;
+    }
+
+    Ok(())
+}
+
+fn rotated_path(path: &Path) -> PathBuf {
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".1");
+    PathBuf::from(rotated)
+}
+
+// The timestamp is in seconds since the Unix epoch, as in the JSON log
+fn format_line(content: &NotifyContent, result: &Result<()>, time: SystemTime) -> String {
+    let timestamp = time
+        .duration_since(UNIX_EPOCH)
+        .map(|since_epoch| since_epoch.as_secs_f64())
+        .unwrap_or_default();
+
+    let mut mentions = content.mentions.iter().collect::<Vec<_>>();
+    mentions.sort();
+
+    json!({
+        "timestamp": timestamp,
+        "title": content.title(),
+        "description": content.description(),
+        "url": content.url,
+        "mentions": mentions.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+        "error": result.as_ref().err().map(|e| format!("{e:#}")),
+    })
+    .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::{Mention, NotifyKind};
+    use crate::tcec::{EngineName, TCEC_URL};
+    use anyhow::anyhow;
+    use std::collections::HashSet;
+    use std::time::Duration;
+
+    fn content(mentions: &[&str]) -> NotifyContent {
+        NotifyContent {
+            kind: NotifyKind::Started,
+            white_player: EngineName::new("c4ke 1.1"),
+            black_player: EngineName::new("Minic 3.44"),
+            white_elo: None,
+            black_elo: None,
+            tournament: "TCEC Season 29 - Category 1 Playoff".to_string(),
+            round: None,
+            url: TCEC_URL.to_string(),
+            eco: None,
+            opening: None,
+            variation: None,
+            time_control: None,
+            book_length: None,
+            eval: None,
+            followed_side: None,
+            mentions: mentions
+                .iter()
+                .map(|id| Mention::parse(id))
+                .collect::<HashSet<_>>(),
+            engine_template: None,
+        }
+    }
+
+    #[test]
+    fn test_audit_line_records_mentions_and_errors() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+
+        let line = format_line(&content(&["5678", "1234"]), &Ok(()), time);
+        assert!(line.contains(r#""mentions":["<@!1234>","<@!5678>"]"#));
+        assert!(line.contains(r#""error":null"#));
+        assert!(line.contains(r#""timestamp":1700000000.5"#));
+        assert!(
+            format_line(&content(&[]), &Err(anyhow!("rate limited")), time)
+                .contains(r#""error":"rate limited""#)
+        );
+    }
+
+    #[test]
+    fn test_record_appends_lines() {
+        let path = std::env::temp_dir().join(format!(
+            "tcec-notifier-test-audit-{}.log",
+            std::process::id()
+        ));
+
+        record(&path, &[content(&["1234"])], &Ok(())).unwrap();
+        record(&path, &[content(&[]), content(&["role:5678"])], &Ok(())).unwrap();
+        let audit_log = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(audit_log.lines().count(), 3);
+    }
+}
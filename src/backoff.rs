@@ -0,0 +1,168 @@
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::time::Duration;
+
+const JITTER_FRACTION: f64 = 0.1;
+
+/// Tracks consecutive failures of an operation, doubling the delay before the next attempt
+/// for each failure up to a maximum.
+pub struct Backoff {
+    base: Duration,
+    max: Duration,
+    failures: u32,
+}
+
+impl Backoff {
+    pub fn new(base: Duration, max: Duration) -> Self {
+        Self {
+            base,
+            max,
+            failures: 0,
+        }
+    }
+
+    pub fn delay(&self) -> Duration {
+        let multiplier = 2u32.saturating_pow(self.failures.saturating_sub(1));
+        self.base.saturating_mul(multiplier).min(self.max)
+    }
+
+    /// Records a failure, returning whether this moved us on to a new backoff step.
+    pub fn record_failure(&mut self) -> bool {
+        let previous_delay = self.delay();
+        self.failures = self.failures.saturating_add(1);
+
+        self.failures == 1 || self.delay() != previous_delay
+    }
+
+    /// Records a success, returning the number of consecutive failures that preceded it.
+    pub fn record_success(&mut self) -> u32 {
+        std::mem::take(&mut self.failures)
+    }
+}
+
+/// Randomly lengthen or shorten a delay by up to 10%, so instances started at the same time
+/// drift apart rather than polling in lockstep. The delay is never shortened below `floor`.
+pub fn jitter(delay: Duration, floor: Duration) -> Duration {
+    // Every RandomState is seeded differently, which is random enough to spread out polls
+    let random = RandomState::new().build_hasher().finish() as f64 / u64::MAX as f64;
+    let factor = 1.0 + JITTER_FRACTION * (2.0 * random - 1.0);
+
+    delay.mul_f64(factor).max(floor.min(delay))
+}
+
+/// Try an operation up to `attempts` times, waiting between failures according to `backoff`.
+/// The last error is returned if every attempt fails.
+pub fn retry<T, E>(
+    attempts: u32,
+    backoff: Backoff,
+    operation: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    retry_if(attempts, backoff, |_| true, operation)
+}
+
+/// As `retry`, but errors which `is_retryable` rejects are returned straight away
+pub fn retry_if<T, E>(
+    attempts: u32,
+    mut backoff: Backoff,
+    is_retryable: impl Fn(&E) -> bool,
+    mut operation: impl FnMut() -> Result<T, E>,
+) -> Result<T, E> {
+    let mut attempt = 1;
+
+    loop {
+        match operation() {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt >= attempts || !is_retryable(&e) => return Err(e),
+            Err(_) => {
+                backoff.record_failure();
+                std::thread::sleep(backoff.delay());
+                attempt += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_doubles_up_to_max() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(100));
+        assert_eq!(backoff.delay(), Duration::from_secs(30));
+
+        assert!(backoff.record_failure());
+        assert_eq!(backoff.delay(), Duration::from_secs(30));
+
+        assert!(backoff.record_failure());
+        assert_eq!(backoff.delay(), Duration::from_secs(60));
+
+        assert!(backoff.record_failure());
+        assert_eq!(backoff.delay(), Duration::from_secs(100));
+
+        assert!(!backoff.record_failure());
+        assert_eq!(backoff.delay(), Duration::from_secs(100));
+    }
+
+    #[test]
+    fn test_success_resets_delay() {
+        let mut backoff = Backoff::new(Duration::from_secs(30), Duration::from_secs(600));
+        backoff.record_failure();
+        backoff.record_failure();
+
+        assert_eq!(backoff.record_success(), 2);
+        assert_eq!(backoff.delay(), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_jitter_is_bounded() {
+        let delay = Duration::from_secs(30);
+
+        for _ in 0..100 {
+            let jittered = jitter(delay, Duration::ZERO);
+            assert!(jittered >= Duration::from_secs(27) && jittered <= Duration::from_secs(33));
+
+            assert!(jitter(delay, Duration::from_secs(29)) >= Duration::from_secs(29));
+            assert!(jitter(delay, Duration::from_secs(60)) >= delay);
+        }
+    }
+
+    #[test]
+    fn test_retry_stops_after_success_or_last_attempt() {
+        let no_delay = || Backoff::new(Duration::ZERO, Duration::ZERO);
+
+        let mut calls = 0;
+        let result = retry(3, no_delay(), || {
+            calls += 1;
+            if calls < 2 {
+                Err("failed")
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result, Ok(2));
+
+        let mut calls = 0;
+        let result: Result<(), _> = retry(3, no_delay(), || {
+            calls += 1;
+            Err(calls)
+        });
+        assert_eq!(result, Err(3));
+    }
+
+    #[test]
+    fn test_retry_if_stops_at_unretryable_error() {
+        let mut calls = 0;
+        let result: Result<(), _> = retry_if(
+            3,
+            Backoff::new(Duration::ZERO, Duration::ZERO),
+            |e: &&str| *e != "fatal",
+            || {
+                calls += 1;
+                Err(if calls < 2 { "blip" } else { "fatal" })
+            },
+        );
+
+        assert_eq!(result, Err("fatal"));
+        assert_eq!(calls, 2);
+    }
+}
@@ -1,62 +1,1688 @@
-use anyhow::Result;
+use crate::backoff::{self, Backoff};
+use crate::http::{HttpClient, HttpStatusError, ReqwestClient, DEFAULT_USER_AGENT};
+use crate::notify::{Mention, MessageTemplate};
+use crate::state::DEFAULT_STATE_PATH;
+use crate::tcec::{normalize_engine_name, EngineName, TCEC_PGN_URL, TCEC_URL};
+use crate::tcec::{Aliases, MatchMode, MatchOptions, StripPatterns, DEFAULT_FUZZY_THRESHOLD};
+use crate::tcec_pgn::Pgn;
+use anyhow::{anyhow, bail, Context, Result};
+use regex::Regex;
+use reqwest::header::AUTHORIZATION;
 use reqwest::Url;
 use serde::Deserialize;
 use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug)]
+const DEFAULT_POLL_DELAY: Duration = Duration::from_secs(30);
+pub const MIN_POLL_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_HTTP_TIMEOUT: Duration = Duration::from_secs(15);
+const CONFIG_FETCH_ATTEMPTS: u32 = 3;
+const CONFIG_FETCH_RETRY_DELAY: Duration = Duration::from_secs(1);
+const CONFIG_FETCH_MAX_RETRY_DELAY: Duration = Duration::from_secs(5);
+const DEFAULT_DISCORD_USERNAME: &str = "tcec-notifier";
+const DEFAULT_CONFIG_RELOAD_DELAY: Duration = Duration::ZERO;
+const DEFAULT_CONFIG_STABLE_FETCHES: u32 = 2;
+
+// The newest config file format we understand. Configs without a version are from before
+// versions were added, which is version 1.
+const CONFIG_VERSION: u32 = 1;
+
+/// Following this in place of an engine's name follows every game, whoever is playing
+pub const ALL_ENGINES: &str = "*";
+
+// TCEC runs a handful of boards at most, so this is only reached by a broken PGN
+const DEFAULT_MAX_GAMES_PER_POLL: usize = 100;
+const DEFAULT_STALE_GAME_DAYS: u64 = 2;
+
+#[derive(Debug, Default)]
 pub struct NotifyConfig {
-    pub engines: HashMap<String, HashSet<String>>,
+    /// The users following each engine, along with the tournaments they want to hear about
+    pub engines: HashMap<String, HashMap<String, EventFilter>>,
+
+    /// The users following each pair of engines, only when they play each other
+    pub matchups: HashMap<[String; 2], HashSet<String>>,
+
+    /// The users watching for each opening, given as an ECO code or part of the opening's name
+    pub openings: HashMap<String, HashSet<String>>,
+
+    /// The tournaments each user follows every game in, whoever is playing
+    pub events: HashMap<String, EventFilter>,
+
+    /// Messages to send instead of the usual one when particular engines are playing
+    pub messages: HashMap<String, MessageTemplate>,
+
+    /// Engines whose games are never notified about, whoever else is playing
+    pub ignored_engines: Vec<String>,
+
+    /// How long to wait after notifying that an engine has started a game before doing so again
+    pub cooldowns: HashMap<String, Duration>,
+    pub match_options: MatchOptions,
+
+    /// Likely mistakes in the config, which are reported when it's loaded
+    pub warnings: Vec<String>,
+}
+
+// Warnings are derived from the rest of the config, so don't count towards it changing
+impl PartialEq for NotifyConfig {
+    fn eq(&self, other: &Self) -> bool {
+        self.engines == other.engines
+            && self.matchups == other.matchups
+            && self.openings == other.openings
+            && self.events == other.events
+            && self.messages == other.messages
+            && self.ignored_engines == other.ignored_engines
+            && self.cooldowns == other.cooldowns
+            && self.match_options == other.match_options
+    }
+}
+
+impl NotifyConfig {
+    pub fn ignores(&self, game: &Pgn) -> bool {
+        self.ignored_engines
+            .iter()
+            .any(|engine| game.has_player(engine, &self.match_options))
+    }
+
+    /// A readable overview of who follows what, e.g. for logging when the config is loaded:
+    ///
+    /// ```text
+    /// Following 2 engines for 3 users
+    ///   Leela: 1 user
+    ///   Stockfish: 2 users
+    /// ```
+    pub fn summary(&self) -> String {
+        let users = self
+            .engines
+            .values()
+            .flat_map(|users| users.keys())
+            .chain(self.matchups.values().flatten())
+            .chain(self.openings.values().flatten())
+            .chain(self.events.keys())
+            .collect::<HashSet<_>>();
+
+        let mut engines = self
+            .engines
+            .iter()
+            .map(|(engine, users)| (engine.as_str(), users.len()))
+            .collect::<Vec<_>>();
+        engines.sort_by_key(|(engine, _)| engine.to_lowercase());
+
+        // Following every game isn't following a particular engine
+        let engine_count = engines
+            .iter()
+            .filter(|(engine, _)| *engine != ALL_ENGINES)
+            .count();
+
+        let mut summary = format!(
+            "Following {} for {}",
+            count(engine_count, "engine"),
+            count(users.len(), "user")
+        );
+
+        for (engine, followers) in engines {
+            let engine = match engine {
+                ALL_ENGINES => "Every game",
+                engine => engine,
+            };
+            summary += &format!("\n  {engine}: {}", count(followers, "user"));
+        }
+
+        summary
+    }
+
+    /// The users following every game in the event, rather than particular engines
+    pub fn all_engines_users(&self, event: &str) -> Vec<Mention> {
+        self.engines
+            .get(ALL_ENGINES)
+            .into_iter()
+            .flatten()
+            .filter(|(_, events)| events.matches(event))
+            .map(|(user, _)| Mention::parse(user))
+            .collect()
+    }
+
+    /// The cooldown for an engine, taking the longest if it matches more than one
+    pub fn cooldown(&self, engine: &EngineName) -> Option<Duration> {
+        self.cooldowns
+            .iter()
+            .filter(|(name, _)| engine.matches(name, &self.match_options))
+            .map(|(_, cooldown)| *cooldown)
+            .max()
+    }
+}
+
+/// The tournaments a user wants to be notified about for an engine they follow
+#[derive(Debug, Clone)]
+pub enum EventFilter {
+    All,
+    Events(Vec<Regex>),
+}
+
+impl EventFilter {
+    fn new(events: &[String]) -> Result<Self> {
+        let events = events
+            .iter()
+            .map(|event| {
+                Regex::new(event).with_context(|| format!("Invalid event pattern `{event}`"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(EventFilter::Events(events))
+    }
+
+    pub fn matches(&self, event: &str) -> bool {
+        match self {
+            EventFilter::All => true,
+            EventFilter::Events(events) => events.iter().any(|pattern| pattern.is_match(event)),
+        }
+    }
+
+    // A user may follow the same engine more than once, in which case they want to hear about
+    // any tournament matched by either follow
+    fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (EventFilter::Events(mut events), EventFilter::Events(other_events)) => {
+                events.extend(other_events);
+                EventFilter::Events(events)
+            }
+            _ => EventFilter::All,
+        }
+    }
+}
+
+impl PartialEq for EventFilter {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (EventFilter::All, EventFilter::All) => true,
+            (EventFilter::Events(events), EventFilter::Events(other_events)) => events
+                .iter()
+                .map(Regex::as_str)
+                .eq(other_events.iter().map(Regex::as_str)),
+            _ => false,
+        }
+    }
+}
+
+/// Substrings of the tournaments this instance is limited to. Empty allows every tournament.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct EventAllowlist(Vec<String>);
+
+impl EventAllowlist {
+    fn parse(allowlist: &str) -> Self {
+        Self(
+            allowlist
+                .split(',')
+                .map(|event| event.trim().to_lowercase())
+                .filter(|event| !event.is_empty())
+                .collect(),
+        )
+    }
+
+    pub fn allows(&self, event: &str) -> bool {
+        let event = event.to_lowercase();
+        self.0.is_empty() || self.0.iter().any(|allowed| event.contains(allowed))
+    }
+}
+
+/// A daily window, in UTC, during which notifications aren't sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuietHours {
+    // Minutes since midnight
+    start: u32,
+    end: u32,
+}
+
+impl QuietHours {
+    /// Parse a window like `22:00-07:00`
+    fn parse(quiet_hours: &str) -> Option<Self> {
+        let (start, end) = quiet_hours.split_once('-')?;
+
+        Some(Self {
+            start: parse_time_of_day(start)?,
+            end: parse_time_of_day(end)?,
+        })
+    }
+
+    pub fn is_quiet_now(&self) -> bool {
+        let since_epoch = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+
+        self.contains((since_epoch.as_secs() % (24 * 60 * 60) / 60) as u32)
+    }
+
+    fn contains(&self, minute_of_day: u32) -> bool {
+        if self.start <= self.end {
+            (self.start..self.end).contains(&minute_of_day)
+        } else {
+            // The window wraps around midnight
+            minute_of_day >= self.start || minute_of_day < self.end
+        }
+    }
+}
+
+fn parse_time_of_day(time: &str) -> Option<u32> {
+    let (hours, minutes) = time.trim().split_once(':')?;
+    let hours = hours.parse::<u32>().ok().filter(|hours| *hours < 24)?;
+    let minutes = minutes
+        .parse::<u32>()
+        .ok()
+        .filter(|minutes| *minutes < 60)?;
+
+    Some(hours * 60 + minutes)
+}
+
+pub enum ConfigSource {
+    Url {
+        url: Url,
+
+        /// Sent as the `Authorization` header, for configs on a private server
+        auth: Option<String>,
+    },
+    File(PathBuf),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+pub struct TelegramConfig {
+    pub bot_token: String,
+    pub chat_id: String,
+}
+
+/// Who messages sent to Discord webhooks appear to be from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscordIdentity {
+    pub username: String,
+    pub avatar_url: Option<String>,
+}
+
+impl Default for DiscordIdentity {
+    fn default() -> Self {
+        Self {
+            username: DEFAULT_DISCORD_USERNAME.to_string(),
+            avatar_url: None,
+        }
+    }
 }
 
 pub struct Config {
-    pub config_url: Url,
-    pub notify_webhook: String,
+    pub config_source: ConfigSource,
+
+    /// Where to fetch the live games from, which can be a mirror or a test server
+    pub pgn_url: Url,
+
+    /// Where to link to for games, when their PGN doesn't say where they're being played
+    pub site_url: Url,
+
+    /// A PGN file to read instead of fetching the live game, for testing
+    pub pgn_file: Option<PathBuf>,
+
+    /// Where to keep the games we've seen. The rest of our state is kept alongside it.
+    pub state_path: PathBuf,
+
+    /// Keep state in memory only, so nothing is read from or written to `state_path`
+    pub no_state: bool,
+
+    /// Consider every game in the live PGN, rather than just the first
+    pub all_boards: bool,
+    pub notify_webhooks: Vec<String>,
+    pub discord_identity: DiscordIdentity,
+    pub slack_webhook: Option<String>,
+    pub telegram: Option<TelegramConfig>,
+    pub message_template: Option<MessageTemplate>,
     pub log_webhook: Option<String>,
+
+    /// Who to ping in the log channel about errors and config changes, if anyone
+    pub admin_mention: Option<Mention>,
+    pub log_format: LogFormat,
+    pub poll_delay: Duration,
+
+    /// How long to wait for a response to any request
+    pub http_timeout: Duration,
+
+    /// Sent with each request, defaulting to `tcec-notifier/<version>`
+    pub user_agent: String,
+
+    /// Used for every request, built from the timeout and user agent
+    pub http_client: ReqwestClient,
+
+    /// How long to wait between re-fetching the notify config. By default it's fetched on every
+    /// poll.
+    pub config_reload_delay: Duration,
+
+    /// How many fetches in a row a changed notify config needs to be the same in before it's
+    /// applied, so half-finished edits aren't
+    pub config_stable_fetches: u32,
+    pub notify_decisive_only: bool,
+    pub notify_in_book: bool,
+
+    /// Games in other tournaments are ignored entirely
+    pub event_allowlist: EventAllowlist,
+
+    /// Games dated longer ago than this are left over from a previous event, so are ignored
+    pub stale_game_age: Duration,
+
+    /// Send games which start in the same poll as a single message
+    pub batch_notifications: bool,
+
+    /// The most games to look at in a single poll, in case the PGN has far more than expected
+    pub max_games_per_poll: usize,
+
+    /// Log notifications instead of sending them
+    pub dry_run: bool,
+
+    /// A file to record every notification sent in, along with who it mentioned
+    pub audit_log: Option<PathBuf>,
+
+    /// Refuse to start if a Discord webhook can't be reached, rather than only warning
+    pub require_valid_webhooks: bool,
+
+    /// When not to send notifications. Games are still tracked, so nothing is sent afterwards.
+    pub quiet_hours: Option<QuietHours>,
+
+    /// Poll a single time and exit, for running from an external scheduler
+    pub run_once: bool,
+
+    /// How far the eval needs to swing between consecutive moves to notify about it
+    pub eval_swing_threshold: Option<f32>,
+
+    /// How little time a followed engine needs to have left to notify that it's in time trouble
+    pub time_trouble_threshold: Option<Duration>,
+
+    /// The port to serve health checks on, if any
+    pub health_port: Option<u16>,
+
+    /// The port to serve Prometheus metrics on, if any
+    pub metrics_port: Option<u16>,
+
+    /// Problems found while loading the config which weren't severe enough to fail startup.
+    /// These are reported once a logger is available.
+    pub warnings: Vec<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ConfigFormat {
+    Json5,
+    Toml,
+}
+
+impl ConfigFormat {
+    fn from_path(path: &Path) -> Self {
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            ConfigFormat::Toml
+        } else {
+            ConfigFormat::Json5
+        }
+    }
+}
+
+/// An engine a user follows, either in every tournament or only in events matching one of the
+/// patterns
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Follow {
+    Engine(String),
+    Scoped { engine: String, events: Vec<String> },
 }
 
 #[derive(Deserialize)]
 struct ConfigFile {
-    pub users: HashMap<String, HashSet<String>>,
+    pub version: Option<u32>,
+
+    pub users: HashMap<String, Vec<Follow>>,
+
+    #[serde(default)]
+    pub matchups: HashMap<String, Vec<[String; 2]>>,
+
+    #[serde(default)]
+    pub openings: HashMap<String, Vec<String>>,
+
+    #[serde(default)]
+    pub events: HashMap<String, Vec<String>>,
+
+    #[serde(default)]
+    pub messages: HashMap<String, String>,
+
+    #[serde(default)]
+    pub ignored_engines: Vec<String>,
+
+    /// In minutes
+    #[serde(default)]
+    pub cooldowns: HashMap<String, u64>,
+
+    #[serde(default)]
+    pub aliases: HashMap<String, String>,
+
+    #[serde(default)]
+    pub match_mode: MatchMode,
+
+    #[serde(default)]
+    pub strip_patterns: Vec<String>,
+
+    pub fuzzy_threshold: Option<usize>,
 }
 
 pub fn get_config() -> Result<Config> {
-    let config_url = std::env::var("TCEC_CONFIG_URL")?;
-    let notify_webhook = std::env::var("TCEC_NOTIFY_WEBHOOK")?;
+    let config_source = get_config_source()?;
+    let notify_webhooks = std::env::var("TCEC_NOTIFY_WEBHOOK")
+        .unwrap_or_default()
+        .split(',')
+        .map(|webhook| webhook.trim().to_string())
+        .filter(|webhook| !webhook.is_empty())
+        .collect::<Vec<_>>();
+
+    let discord_identity = get_discord_identity();
+    let user_agent = std::env::var("TCEC_USER_AGENT")
+        .ok()
+        .filter(|user_agent| !user_agent.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_USER_AGENT.to_string());
+    let slack_webhook = std::env::var("TCEC_SLACK_WEBHOOK").ok();
+    let telegram = get_telegram_config()?;
+
+    if notify_webhooks.is_empty() && slack_webhook.is_none() && telegram.is_none() {
+        bail!("Nothing to notify, set TCEC_NOTIFY_WEBHOOK, TCEC_SLACK_WEBHOOK or TCEC_TELEGRAM_*");
+    }
+
+    let message_template = std::env::var("TCEC_MESSAGE_TEMPLATE")
+        .ok()
+        .map(|template| MessageTemplate::new(&template))
+        .transpose()?;
+
     let log_webhook = std::env::var("TCEC_LOG_WEBHOOK").ok();
+    let admin_mention = std::env::var("TCEC_ADMIN_MENTION")
+        .ok()
+        .filter(|id| !id.trim().is_empty())
+        .map(|id| Mention::parse(id.trim()));
+    let notify_decisive_only = get_flag("TCEC_NOTIFY_DECISIVE_ONLY");
+    let notify_in_book = get_flag("TCEC_NOTIFY_IN_BOOK");
+    let event_allowlist = std::env::var("TCEC_EVENT_ALLOWLIST")
+        .map(|allowlist| EventAllowlist::parse(&allowlist))
+        .unwrap_or_default();
+    let batch_notifications = get_flag("TCEC_BATCH_NOTIFICATIONS");
+    let dry_run = get_flag("TCEC_DRY_RUN");
+    let audit_log = std::env::var("TCEC_AUDIT_LOG").ok().map(PathBuf::from);
+    let require_valid_webhooks = get_flag("TCEC_REQUIRE_VALID_WEBHOOKS");
+    let run_once = get_flag("TCEC_RUN_ONCE");
+    let pgn_url = get_url("TCEC_PGN_URL", TCEC_PGN_URL)?;
+    let site_url = get_url("TCEC_SITE_URL", TCEC_URL)?;
+    let pgn_file = std::env::var("TCEC_PGN_FILE").ok().map(PathBuf::from);
+    let state_path = std::env::var("TCEC_STATE_PATH")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from(DEFAULT_STATE_PATH));
+    let no_state = get_flag("TCEC_NO_STATE");
+    let all_boards = get_flag("TCEC_ALL_BOARDS");
+
+    let mut warnings = vec![];
+    let poll_delay = get_poll_delay(&mut warnings);
+    let http_timeout = get_http_timeout(&mut warnings);
+    let config_reload_delay = get_config_reload_delay(&mut warnings);
+    let config_stable_fetches = get_config_stable_fetches(&mut warnings);
+    let stale_game_age = get_stale_game_age(&mut warnings);
+    let max_games_per_poll = get_max_games_per_poll(&mut warnings);
+    let eval_swing_threshold = get_eval_swing_threshold(&mut warnings);
+    let time_trouble_threshold = get_time_trouble_threshold(&mut warnings);
+    let log_format = get_log_format(&mut warnings);
+    let quiet_hours = get_quiet_hours(&mut warnings);
+    let health_port = get_port("TCEC_HEALTH_PORT", "health checks", &mut warnings);
+    let metrics_port = get_port("TCEC_METRICS_PORT", "metrics", &mut warnings);
 
     Ok(Config {
-        config_url: Url::parse(&config_url)?,
-        notify_webhook,
+        config_source,
+        pgn_url,
+        site_url,
+        pgn_file,
+        state_path,
+        no_state,
+        all_boards,
+        notify_webhooks,
+        discord_identity,
+        slack_webhook,
+        telegram,
+        message_template,
         log_webhook,
+        admin_mention,
+        log_format,
+        poll_delay,
+        http_timeout,
+        http_client: ReqwestClient::new(http_timeout, &user_agent)?,
+        user_agent,
+        config_reload_delay,
+        config_stable_fetches,
+        notify_decisive_only,
+        notify_in_book,
+        event_allowlist,
+        stale_game_age,
+        batch_notifications,
+        max_games_per_poll,
+        dry_run,
+        audit_log,
+        require_valid_webhooks,
+        quiet_hours,
+        run_once,
+        eval_swing_threshold,
+        time_trouble_threshold,
+        health_port,
+        metrics_port,
+        warnings,
+    })
+}
+
+#[cfg(test)]
+impl Config {
+    /// Nothing is read from the environment, and notifications are sent as soon as they happen
+    pub fn for_tests() -> Self {
+        Self {
+            config_source: ConfigSource::File(PathBuf::new()),
+            pgn_url: Url::parse(TCEC_PGN_URL).unwrap(),
+            site_url: Url::parse(TCEC_URL).unwrap(),
+            pgn_file: None,
+            state_path: PathBuf::from(DEFAULT_STATE_PATH),
+            no_state: true,
+            all_boards: false,
+            notify_webhooks: vec![],
+            discord_identity: DiscordIdentity::default(),
+            slack_webhook: None,
+            telegram: None,
+            message_template: None,
+            log_webhook: None,
+            admin_mention: None,
+            log_format: LogFormat::Text,
+            poll_delay: DEFAULT_POLL_DELAY,
+            http_timeout: DEFAULT_HTTP_TIMEOUT,
+            http_client: ReqwestClient::for_tests(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            config_reload_delay: DEFAULT_CONFIG_RELOAD_DELAY,
+            config_stable_fetches: 1,
+            notify_decisive_only: false,
+            notify_in_book: false,
+            event_allowlist: EventAllowlist::default(),
+            // Test games are dated whenever they were written, so none of them are stale
+            stale_game_age: Duration::MAX,
+            batch_notifications: false,
+            max_games_per_poll: DEFAULT_MAX_GAMES_PER_POLL,
+            dry_run: false,
+            audit_log: None,
+            require_valid_webhooks: false,
+            quiet_hours: None,
+            run_once: false,
+            eval_swing_threshold: None,
+            time_trouble_threshold: None,
+            health_port: None,
+            metrics_port: None,
+            warnings: vec![],
+        }
+    }
+}
+
+fn get_config_source() -> Result<ConfigSource> {
+    if let Ok(config_path) = std::env::var("TCEC_CONFIG_PATH") {
+        return Ok(ConfigSource::File(PathBuf::from(config_path)));
+    }
+
+    let config_url = Url::parse(&std::env::var("TCEC_CONFIG_URL")?)?;
+
+    if config_url.scheme() == "file" {
+        let config_path = config_url
+            .to_file_path()
+            .map_err(|_| anyhow!("Invalid config file URL: {}", config_url))?;
+
+        return Ok(ConfigSource::File(config_path));
+    }
+
+    Ok(ConfigSource::Url {
+        url: config_url,
+        auth: std::env::var("TCEC_CONFIG_AUTH").ok(),
     })
 }
 
-pub fn get_notify_config(config: &Config) -> Result<NotifyConfig> {
-    let client = reqwest::blocking::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?;
+fn get_url(name: &str, default: &str) -> Result<Url> {
+    let url = std::env::var(name).unwrap_or_else(|_| default.to_string());
+
+    Url::parse(&url).with_context(|| format!("Invalid {name} `{url}`"))
+}
+
+fn get_telegram_config() -> Result<Option<TelegramConfig>> {
+    let bot_token = std::env::var("TCEC_TELEGRAM_TOKEN").ok();
+    let chat_id = std::env::var("TCEC_TELEGRAM_CHAT").ok();
 
-    let response = client
-        .get(config.config_url.clone())
-        .send()?
-        .error_for_status()?;
+    match (bot_token, chat_id) {
+        (Some(bot_token), Some(chat_id)) => Ok(Some(TelegramConfig { bot_token, chat_id })),
+        (None, None) => Ok(None),
+        _ => bail!("TCEC_TELEGRAM_TOKEN and TCEC_TELEGRAM_CHAT must be set together"),
+    }
+}
+
+fn get_discord_identity() -> DiscordIdentity {
+    let default = DiscordIdentity::default();
+
+    DiscordIdentity {
+        username: std::env::var("TCEC_DISCORD_USERNAME")
+            .ok()
+            .filter(|username| !username.trim().is_empty())
+            .unwrap_or(default.username),
+        avatar_url: std::env::var("TCEC_DISCORD_AVATAR_URL").ok(),
+    }
+}
+
+fn get_quiet_hours(warnings: &mut Vec<String>) -> Option<QuietHours> {
+    let quiet_hours = std::env::var("TCEC_QUIET_HOURS").ok()?;
+
+    let parsed = QuietHours::parse(&quiet_hours);
+    if parsed.is_none() {
+        warnings.push(format!(
+            "Unable to parse TCEC_QUIET_HOURS `{quiet_hours}`, expected e.g. `22:00-07:00`"
+        ));
+    }
+
+    parsed
+}
+
+fn get_flag(name: &str) -> bool {
+    std::env::var(name).is_ok_and(|value| !matches!(value.trim(), "" | "0" | "false"))
+}
 
-    let config_file_contents = response.text()?;
+fn get_eval_swing_threshold(warnings: &mut Vec<String>) -> Option<f32> {
+    let threshold = std::env::var("TCEC_EVAL_SWING_THRESHOLD").ok()?;
 
-    let config_file = serde_json5::from_str::<ConfigFile>(&config_file_contents)?;
+    match threshold.trim().parse::<f32>() {
+        Ok(threshold) if threshold.is_finite() && threshold > 0.0 => Some(threshold),
+        _ => {
+            warnings.push(format!(
+                "Invalid TCEC_EVAL_SWING_THRESHOLD `{threshold}`, not notifying for eval swings"
+            ));
+            None
+        }
+    }
+}
+
+fn get_time_trouble_threshold(warnings: &mut Vec<String>) -> Option<Duration> {
+    let threshold = std::env::var("TCEC_TIME_TROUBLE_SECONDS").ok()?;
+
+    let Ok(seconds) = threshold.trim().parse::<u64>() else {
+        warnings.push(format!(
+            "Invalid TCEC_TIME_TROUBLE_SECONDS `{threshold}`, not notifying for time trouble"
+        ));
+        return None;
+    };
+
+    (seconds > 0).then(|| Duration::from_secs(seconds))
+}
+
+fn get_log_format(warnings: &mut Vec<String>) -> LogFormat {
+    let Ok(log_format) = std::env::var("TCEC_LOG_FORMAT") else {
+        return LogFormat::Text;
+    };
+
+    match log_format.trim() {
+        "json" => LogFormat::Json,
+        "" | "text" => LogFormat::Text,
+        _ => {
+            warnings.push(format!(
+                "Unknown TCEC_LOG_FORMAT `{log_format}`, using text"
+            ));
+            LogFormat::Text
+        }
+    }
+}
+
+fn get_port(name: &str, description: &str, warnings: &mut Vec<String>) -> Option<u16> {
+    let port = std::env::var(name).ok()?;
+
+    let Ok(port) = port.trim().parse::<u16>() else {
+        warnings.push(format!(
+            "Unable to parse {name} `{port}`, not serving {description}"
+        ));
+        return None;
+    };
+
+    Some(port)
+}
+
+fn get_http_timeout(warnings: &mut Vec<String>) -> Duration {
+    let Ok(timeout_seconds) = std::env::var("TCEC_HTTP_TIMEOUT_SECONDS") else {
+        return DEFAULT_HTTP_TIMEOUT;
+    };
+
+    match timeout_seconds.trim().parse::<u64>() {
+        Ok(timeout_seconds) if timeout_seconds > 0 => Duration::from_secs(timeout_seconds),
+        _ => {
+            warnings.push(format!(
+                "Unable to parse TCEC_HTTP_TIMEOUT_SECONDS `{timeout_seconds}`, using {}s",
+                DEFAULT_HTTP_TIMEOUT.as_secs()
+            ));
+            DEFAULT_HTTP_TIMEOUT
+        }
+    }
+}
+
+fn get_config_reload_delay(warnings: &mut Vec<String>) -> Duration {
+    let Ok(reload_seconds) = std::env::var("TCEC_CONFIG_RELOAD_SECONDS") else {
+        return DEFAULT_CONFIG_RELOAD_DELAY;
+    };
+
+    match reload_seconds.trim().parse::<u64>() {
+        Ok(reload_seconds) => Duration::from_secs(reload_seconds),
+        Err(_) => {
+            warnings.push(format!(
+                "Unable to parse TCEC_CONFIG_RELOAD_SECONDS `{reload_seconds}`, reloading each poll"
+            ));
+            DEFAULT_CONFIG_RELOAD_DELAY
+        }
+    }
+}
+
+fn get_config_stable_fetches(warnings: &mut Vec<String>) -> u32 {
+    let Ok(stable_fetches) = std::env::var("TCEC_CONFIG_STABLE_FETCHES") else {
+        return DEFAULT_CONFIG_STABLE_FETCHES;
+    };
+
+    match stable_fetches.trim().parse::<u32>() {
+        Ok(stable_fetches) if stable_fetches > 0 => stable_fetches,
+        _ => {
+            warnings.push(format!(
+                "Invalid TCEC_CONFIG_STABLE_FETCHES `{stable_fetches}`, using {}",
+                DEFAULT_CONFIG_STABLE_FETCHES
+            ));
+            DEFAULT_CONFIG_STABLE_FETCHES
+        }
+    }
+}
+
+fn get_max_games_per_poll(warnings: &mut Vec<String>) -> usize {
+    let Ok(max_games) = std::env::var("TCEC_MAX_GAMES_PER_POLL") else {
+        return DEFAULT_MAX_GAMES_PER_POLL;
+    };
+
+    match max_games.trim().parse::<usize>() {
+        Ok(max_games) if max_games > 0 => max_games,
+        _ => {
+            warnings.push(format!(
+                "Invalid TCEC_MAX_GAMES_PER_POLL `{max_games}`, using {}",
+                DEFAULT_MAX_GAMES_PER_POLL
+            ));
+            DEFAULT_MAX_GAMES_PER_POLL
+        }
+    }
+}
 
-    let mut engines_to_users: HashMap<String, HashSet<String>> = HashMap::new();
+fn get_stale_game_age(warnings: &mut Vec<String>) -> Duration {
+    let default = Duration::from_secs(DEFAULT_STALE_GAME_DAYS * 24 * 60 * 60);
 
-    for (user, engines) in &config_file.users {
-        for engine in engines {
-            engines_to_users
-                .entry(engine.clone())
+    let Ok(stale_game_days) = std::env::var("TCEC_STALE_GAME_DAYS") else {
+        return default;
+    };
+
+    let Ok(days) = stale_game_days.trim().parse::<u64>() else {
+        warnings.push(format!(
+            "Unable to parse TCEC_STALE_GAME_DAYS `{stale_game_days}`, using {} days",
+            DEFAULT_STALE_GAME_DAYS
+        ));
+        return default;
+    };
+
+    match days.checked_mul(24 * 60 * 60) {
+        Some(seconds) if days > 0 => Duration::from_secs(seconds),
+        _ => {
+            warnings.push(format!(
+                "TCEC_STALE_GAME_DAYS of {days} days is out of range, using {} days",
+                DEFAULT_STALE_GAME_DAYS
+            ));
+            default
+        }
+    }
+}
+
+fn get_poll_delay(warnings: &mut Vec<String>) -> Duration {
+    let Ok(poll_seconds) = std::env::var("TCEC_POLL_SECONDS") else {
+        return DEFAULT_POLL_DELAY;
+    };
+
+    let Ok(poll_seconds) = poll_seconds.trim().parse::<u64>() else {
+        warnings.push(format!(
+            "Unable to parse TCEC_POLL_SECONDS `{poll_seconds}`, using {}s",
+            DEFAULT_POLL_DELAY.as_secs()
+        ));
+        return DEFAULT_POLL_DELAY;
+    };
+
+    let poll_delay = Duration::from_secs(poll_seconds);
+
+    if poll_delay < MIN_POLL_DELAY {
+        warnings.push(format!(
+            "TCEC_POLL_SECONDS of {poll_seconds}s is too short, using {}s",
+            MIN_POLL_DELAY.as_secs()
+        ));
+        return MIN_POLL_DELAY;
+    }
+
+    poll_delay
+}
+
+/// Why the config couldn't be fetched, which decides whether it's worth trying again
+#[derive(Debug)]
+enum FetchError {
+    /// The request didn't get through or the server had a problem, which may only be a blip
+    Transient(anyhow::Error),
+
+    /// The server answered with something other than a config, which asking again won't change
+    Rejected(anyhow::Error),
+}
+
+impl FetchError {
+    fn is_transient(&self) -> bool {
+        matches!(self, FetchError::Transient(_))
+    }
+
+    // A rejection won't sort itself out, so it's as much of a problem as a broken config
+    fn into_config_error(self) -> NotifyConfigError {
+        match self {
+            FetchError::Transient(e) => NotifyConfigError::Unavailable(e),
+            FetchError::Rejected(e) => NotifyConfigError::Invalid(e),
+        }
+    }
+}
+
+fn fetch_config_file(
+    client: &dyn HttpClient,
+    config_url: &Url,
+    auth: Option<&str>,
+) -> Result<(String, ConfigFormat), FetchError> {
+    let headers = auth
+        .map(|auth| vec![(AUTHORIZATION, auth)])
+        .unwrap_or_default();
+
+    let response = client.get(config_url.as_str(), &headers).map_err(|e| {
+        let is_client_error = e
+            .downcast_ref::<HttpStatusError>()
+            .is_some_and(HttpStatusError::is_client_error);
+
+        if is_client_error {
+            FetchError::Rejected(e)
+        } else {
+            FetchError::Transient(e)
+        }
+    })?;
+
+    // Redirects aren't followed, so they'd otherwise show up as an empty config
+    if (300..400).contains(&response.status) {
+        return Err(FetchError::Rejected(anyhow!(
+            "Config URL redirected ({}) to {}, use that URL instead",
+            response.status,
+            response
+                .location
+                .as_deref()
+                .unwrap_or("an unknown location")
+        )));
+    }
+
+    // Probably a login or captcha page, which would otherwise be a confusing parse error
+    let is_html_content_type = response
+        .content_type
+        .as_ref()
+        .is_some_and(|content_type| content_type.contains("html"));
+
+    if is_html_content_type || response.body.trim_start().starts_with('<') {
+        return Err(FetchError::Rejected(anyhow!(
+            "Config URL returned HTML rather than a config, check the URL and authentication"
+        )));
+    }
+
+    let is_toml_content_type = response
+        .content_type
+        .is_some_and(|content_type| content_type.contains("toml"));
+
+    let format = if is_toml_content_type {
+        ConfigFormat::Toml
+    } else {
+        ConfigFormat::from_path(Path::new(config_url.path()))
+    };
+
+    Ok((response.body, format))
+}
+
+fn parse_config_file(contents: &str, format: ConfigFormat) -> Result<ConfigFile> {
+    let config_file = match format {
+        ConfigFormat::Json5 => serde_json5::from_str::<ConfigFile>(contents)?,
+        ConfigFormat::Toml => toml::from_str::<ConfigFile>(contents)?,
+    };
+
+    Ok(config_file)
+}
+
+/// Why the notify config couldn't be loaded
+#[derive(Debug)]
+pub enum NotifyConfigError {
+    /// The config couldn't be fetched, which is likely to sort itself out
+    Unavailable(anyhow::Error),
+
+    /// The config isn't valid, or what was fetched isn't a config at all (e.g. a login page or a
+    /// 404), which needs fixing by whoever last edited it
+    Invalid(anyhow::Error),
+}
+
+impl std::fmt::Display for NotifyConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NotifyConfigError::Unavailable(e) => write!(f, "Unable to fetch config: {e}"),
+            NotifyConfigError::Invalid(e) => write!(f, "Invalid config: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for NotifyConfigError {}
+
+pub fn get_notify_config(
+    config: &Config,
+    client: &dyn HttpClient,
+) -> Result<NotifyConfig, NotifyConfigError> {
+    load_notify_config(&config.config_source, client)
+}
+
+/// Holds back a changed notify config until it's been fetched the same several times in a row
+pub struct ConfigDebounce {
+    stable_fetches: u32,
+
+    // The changed config, and how many fetches in a row it's been seen in
+    candidate: Option<(NotifyConfig, u32)>,
+}
+
+impl ConfigDebounce {
+    pub fn new(stable_fetches: u32) -> Self {
+        Self {
+            stable_fetches,
+            candidate: None,
+        }
+    }
+
+    /// Record a fetched config, returning it once it's stable and should replace the current one
+    pub fn update(
+        &mut self,
+        current: &NotifyConfig,
+        fetched: NotifyConfig,
+    ) -> Option<NotifyConfig> {
+        // An edit which was reverted before being applied doesn't need applying
+        if *current == fetched {
+            self.candidate = None;
+            return None;
+        }
+
+        let fetches = match self.candidate.take() {
+            Some((candidate, fetches)) if candidate == fetched => fetches + 1,
+            _ => 1,
+        };
+
+        if fetches >= self.stable_fetches {
+            return Some(fetched);
+        }
+
+        self.candidate = Some((fetched, fetches));
+        None
+    }
+}
+
+fn load_notify_config(
+    source: &ConfigSource,
+    client: &dyn HttpClient,
+) -> Result<NotifyConfig, NotifyConfigError> {
+    let (config_file_contents, format) = match source {
+        // Retry a blip rather than keeping a stale config until the next reload
+        ConfigSource::Url { url, auth } => backoff::retry_if(
+            CONFIG_FETCH_ATTEMPTS,
+            Backoff::new(CONFIG_FETCH_RETRY_DELAY, CONFIG_FETCH_MAX_RETRY_DELAY),
+            FetchError::is_transient,
+            || fetch_config_file(client, url, auth.as_deref()),
+        )
+        .map_err(FetchError::into_config_error)?,
+        ConfigSource::File(config_path) => (
+            std::fs::read_to_string(config_path)
+                .map_err(|e| NotifyConfigError::Unavailable(e.into()))?,
+            ConfigFormat::from_path(config_path),
+        ),
+    };
+
+    parse_config_file(&config_file_contents, format)
+        .and_then(|config_file| build_notify_config(&config_file))
+        .map_err(NotifyConfigError::Invalid)
+}
+
+fn check_config_version(version: Option<u32>) -> Result<()> {
+    match version.unwrap_or(1) {
+        0 => bail!("Config version 0 is invalid, versions start at 1"),
+        version if version > CONFIG_VERSION => bail!(
+            "Config version {version} is newer than the supported version {CONFIG_VERSION}, \
+             tcec-notifier needs updating to read it"
+        ),
+        _ => Ok(()),
+    }
+}
+
+// e.g. `1 user` or `2 users`
+fn count(n: usize, noun: &str) -> String {
+    match n {
+        1 => format!("1 {noun}"),
+        n => format!("{n} {noun}s"),
+    }
+}
+
+/// Pairs of distinct names which normalize to the same engine, e.g. "Stockfish" and "stockfish 17"
+fn colliding_engine_names<'a>(
+    names: impl Iterator<Item = &'a String>,
+    strip_patterns: &StripPatterns,
+) -> Vec<[&'a str; 2]> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    let mut collisions = vec![];
+
+    for name in names {
+        let normalized = normalize_engine_name(name, strip_patterns);
+
+        match seen.get(&normalized) {
+            Some(&first) if first != name => collisions.push([first, name.as_str()]),
+            Some(_) => {}
+            None => {
+                seen.insert(normalized, name);
+            }
+        }
+    }
+
+    collisions
+}
+
+fn build_notify_config(config_file: &ConfigFile) -> Result<NotifyConfig> {
+    check_config_version(config_file.version)?;
+
+    // Needed up front, as they decide which names are the same engine
+    let strip_patterns = StripPatterns::new(&config_file.strip_patterns)?;
+
+    let mut engines_to_users: HashMap<String, HashMap<String, EventFilter>> = HashMap::new();
+    let mut warnings = vec![];
+
+    for (user, follows) in &config_file.users {
+        if follows.is_empty() {
+            warnings.push(format!("User `{user}` doesn't follow any engines"));
+        }
+
+        let mut unscoped_engines = HashSet::new();
+
+        for follow in follows {
+            let (engine, filter) = match follow {
+                Follow::Engine(engine) => (engine, EventFilter::All),
+                Follow::Scoped { engine, events } => (engine, EventFilter::new(events)?),
+            };
+
+            if engine.trim().is_empty() {
+                warnings.push(format!(
+                    "User `{user}` follows a blank engine name, which matches every game"
+                ));
+            }
+
+            // Following an engine in several scoped ways is fine, but listing it twice isn't
+            if matches!(follow, Follow::Engine(_)) && !unscoped_engines.insert(engine) {
+                warnings.push(format!("User `{user}` follows `{engine}` more than once"));
+            }
+
+            let users = engines_to_users.entry(engine.clone()).or_default();
+            let filter = match users.remove(user) {
+                Some(existing) => existing.merge(filter),
+                None => filter,
+            };
+            users.insert(user.clone(), filter);
+        }
+
+        let engines = follows.iter().map(|follow| match follow {
+            Follow::Engine(engine) | Follow::Scoped { engine, .. } => engine,
+        });
+        for [first, second] in colliding_engine_names(engines, &strip_patterns) {
+            warnings.push(format!(
+                "User `{user}` follows `{first}` and `{second}`, which are the same engine"
+            ));
+        }
+    }
+
+    for [first, second] in colliding_engine_names(config_file.cooldowns.keys(), &strip_patterns) {
+        warnings.push(format!(
+            "Cooldowns are given for `{first}` and `{second}`, which are the same engine"
+        ));
+    }
+
+    let mut cooldowns = HashMap::new();
+
+    for (engine, &minutes) in &config_file.cooldowns {
+        match minutes.checked_mul(60) {
+            Some(seconds) => {
+                cooldowns.insert(engine.clone(), Duration::from_secs(seconds));
+            }
+            None => warnings.push(format!(
+                "Cooldown of {minutes} minutes for `{engine}` is too long, ignoring it"
+            )),
+        }
+    }
+
+    let mut matchups_to_users: HashMap<[String; 2], HashSet<String>> = HashMap::new();
+
+    for (user, matchups) in &config_file.matchups {
+        for matchup in matchups {
+            // Colors don't matter, so the same pair given either way round is the same matchup
+            let mut matchup = matchup.clone();
+            matchup.sort();
+
+            matchups_to_users
+                .entry(matchup)
                 .or_default()
                 .insert(user.clone());
         }
     }
 
+    let mut openings_to_users: HashMap<String, HashSet<String>> = HashMap::new();
+
+    for (user, openings) in &config_file.openings {
+        for opening in openings {
+            openings_to_users
+                .entry(opening.clone())
+                .or_default()
+                .insert(user.clone());
+        }
+    }
+
+    let events = config_file
+        .events
+        .iter()
+        .map(|(user, events)| Ok((user.clone(), EventFilter::new(events)?)))
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    let messages = config_file
+        .messages
+        .iter()
+        .map(|(engine, message)| {
+            let template = MessageTemplate::new(message)
+                .with_context(|| format!("Invalid message for `{engine}`"))?;
+            Ok((engine.clone(), template))
+        })
+        .collect::<Result<HashMap<_, _>>>()?;
+
+    // Users come out of a map in no particular order, so sort for stable output
+    warnings.sort();
+
     Ok(NotifyConfig {
         engines: engines_to_users,
+        matchups: matchups_to_users,
+        openings: openings_to_users,
+        events,
+        messages,
+        ignored_engines: config_file.ignored_engines.clone(),
+        cooldowns,
+        match_options: MatchOptions {
+            mode: config_file.match_mode,
+            aliases: Aliases::new(&config_file.aliases, &strip_patterns),
+            strip_patterns,
+            fuzzy_threshold: config_file
+                .fuzzy_threshold
+                .unwrap_or(DEFAULT_FUZZY_THRESHOLD),
+        },
+        warnings,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{FakeHttpClient, HttpResponse, ReqwestClient};
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn test_toml_and_json5_configs_are_equivalent() {
+        let json5_config = r#"{
+  "users": {
+    "123": ["Stockfish", "Leela"], // Both of them
+    "456": ["Stockfish"],
+  }
+}"#;
+
+        let toml_config = r#"[users]
+"123" = ["Stockfish", "Leela"] # Both of them
+"456" = ["Stockfish"]
+"#;
+
+        let json5_notify_config =
+            build_notify_config(&parse_config_file(json5_config, ConfigFormat::Json5).unwrap())
+                .unwrap();
+        let toml_notify_config =
+            build_notify_config(&parse_config_file(toml_config, ConfigFormat::Toml).unwrap())
+                .unwrap();
+
+        assert_eq!(json5_notify_config.engines["Stockfish"].len(), 2);
+        assert_eq!(json5_notify_config, toml_notify_config);
+    }
+
+    #[test]
+    fn test_scoped_follows_only_match_their_events() {
+        let config = r#"{
+  "users": {
+    "123": [{ "engine": "Stockfish", "events": ["Superfinal"] }],
+    "456": ["Stockfish"],
+  }
+}"#;
+
+        let notify_config =
+            build_notify_config(&parse_config_file(config, ConfigFormat::Json5).unwrap()).unwrap();
+        let users = &notify_config.engines["Stockfish"];
+
+        assert!(users["123"].matches("TCEC Season 27 - Superfinal"));
+        assert!(!users["123"].matches("TCEC Season 27 - League 1"));
+        assert!(users["456"].matches("TCEC Season 27 - League 1"));
+    }
+
+    #[test]
+    fn test_event_follows_are_kept_apart_from_engines() {
+        let config = r#"{
+  "users": {},
+  "events": {
+    "123": ["Superfinal", "Cup"],
+  }
+}"#;
+
+        let notify_config =
+            build_notify_config(&parse_config_file(config, ConfigFormat::Json5).unwrap()).unwrap();
+
+        assert!(notify_config.engines.is_empty());
+        assert!(notify_config.events["123"].matches("TCEC Season 27 - Superfinal"));
+        assert!(notify_config.events["123"].matches("TCEC Cup 14"));
+        assert!(!notify_config.events["123"].matches("TCEC Season 27 - League 1"));
+    }
+
+    #[test]
+    fn test_merging_event_filters() {
+        let superfinal = EventFilter::new(&["Superfinal".to_string()]).unwrap();
+        let cup = EventFilter::new(&["Cup".to_string()]).unwrap();
+
+        let merged = superfinal.clone().merge(cup);
+        assert!(merged.matches("TCEC Season 27 - Superfinal"));
+        assert!(merged.matches("TCEC Cup 14"));
+        assert!(!merged.matches("TCEC Season 27 - League 1"));
+
+        assert_eq!(superfinal.merge(EventFilter::All), EventFilter::All);
+    }
+
+    #[test]
+    fn test_config_format_from_path() {
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/configs/tcec.toml")),
+            ConfigFormat::Toml
+        );
+        assert_eq!(
+            ConfigFormat::from_path(Path::new("/configs/tcec.json5")),
+            ConfigFormat::Json5
+        );
+    }
+
+    #[test]
+    fn test_event_allowlist_matches_case_insensitive_substrings() {
+        let allowlist = EventAllowlist::parse("superfinal, Cup ,");
+
+        assert!(allowlist.allows("TCEC Season 27 - Superfinal"));
+        assert!(allowlist.allows("TCEC Cup 15"));
+        assert!(!allowlist.allows("TCEC Season 27 - League 1"));
+        assert!(EventAllowlist::default().allows("TCEC Season 27 - League 1"));
+    }
+
+    #[test]
+    fn test_notify_config_errors_distinguish_missing_from_invalid() {
+        let path = std::env::temp_dir().join(format!(
+            "tcec-notifier-test-config-{}.json5",
+            std::process::id()
+        ));
+        let source = ConfigSource::File(path.clone());
+
+        let client = FakeHttpClient::new(vec![]);
+
+        let missing = load_notify_config(&source, &client);
+        assert!(matches!(missing, Err(NotifyConfigError::Unavailable(_))));
+
+        std::fs::write(&path, r#"{ "users": { "123": ["Stockfish" } }"#).unwrap();
+        let invalid = load_notify_config(&source, &client);
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(matches!(invalid, Err(NotifyConfigError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_config_version_defaults_to_1() {
+        assert!(check_config_version(None).is_ok());
+        assert!(check_config_version(Some(CONFIG_VERSION)).is_ok());
+        assert!(check_config_version(Some(0)).is_err());
+        assert!(check_config_version(Some(CONFIG_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn test_warns_about_likely_mistakes() {
+        let config = r#"{
+  "users": {
+    "123": ["Stockfish", "Leela", "Stockfish"],
+    "456": [],
+    "789": [
+      { "engine": "Stockfish", "events": ["Superfinal"] },
+      { "engine": "Stockfish", "events": ["Cup"] },
+    ],
+    "1011": ["Leela", "leela  Chess"],
+    "1213": ["Stockfish", "stockfish 17"],
+  },
+  "cooldowns": { "Leela": 60, "Stockfish": 1000000000000000000 }
+}"#;
+
+        let notify_config =
+            build_notify_config(&parse_config_file(config, ConfigFormat::Json5).unwrap()).unwrap();
+
+        assert_eq!(
+            notify_config.cooldowns.keys().collect::<Vec<_>>(),
+            vec!["Leela"]
+        );
+        assert_eq!(
+            notify_config.warnings,
+            vec![
+                "Cooldown of 1000000000000000000 minutes for `Stockfish` is too long, ignoring it",
+                "User `1213` follows `Stockfish` and `stockfish 17`, which are the same engine",
+                "User `123` follows `Stockfish` more than once",
+                "User `456` doesn't follow any engines",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_colliding_engine_names() {
+        let names = [
+            "Stockfish",
+            "Leela Chess",
+            "stockfish 17",
+            "Stockfish",
+            "LEELA  chess",
+            "Foo dev-1",
+            "Foo dev-2",
+        ]
+        .map(String::from);
+
+        assert_eq!(
+            colliding_engine_names(names.iter(), &StripPatterns::default()),
+            vec![
+                ["Stockfish", "stockfish 17"],
+                ["Leela Chess", "LEELA  chess"]
+            ]
+        );
+
+        let dev_builds = StripPatterns::new(&[r" dev-\d+".to_string()]).unwrap();
+        assert_eq!(
+            colliding_engine_names(names.iter(), &dev_builds),
+            vec![
+                ["Stockfish", "stockfish 17"],
+                ["Leela Chess", "LEELA  chess"],
+                ["Foo dev-1", "Foo dev-2"]
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_engines_users_follow_every_game() {
+        let superfinal = EventFilter::new(&["Superfinal".to_string()]).unwrap();
+        let notify_config = NotifyConfig {
+            engines: HashMap::from([
+                (
+                    ALL_ENGINES.to_string(),
+                    HashMap::from([
+                        ("123".to_string(), EventFilter::All),
+                        ("456".to_string(), superfinal),
+                    ]),
+                ),
+                (
+                    "Stockfish".to_string(),
+                    HashMap::from([("789".to_string(), EventFilter::All)]),
+                ),
+            ]),
+            matchups: HashMap::new(),
+            openings: HashMap::new(),
+            events: HashMap::new(),
+            messages: HashMap::new(),
+            ignored_engines: vec![],
+            cooldowns: HashMap::new(),
+            match_options: MatchOptions::default(),
+            warnings: vec![],
+        };
+
+        assert_eq!(
+            notify_config.all_engines_users("TCEC Season 29 - Category 1 Playoff"),
+            vec![Mention::parse("123")]
+        );
+
+        let mut superfinal_users = notify_config.all_engines_users("TCEC Season 29 - Superfinal");
+        superfinal_users.sort();
+        assert_eq!(
+            superfinal_users,
+            vec![Mention::parse("123"), Mention::parse("456")]
+        );
+    }
+
+    #[test]
+    fn test_summary_counts_engines_and_users() {
+        let followers = |users: &[&str]| {
+            users
+                .iter()
+                .map(|user| (user.to_string(), EventFilter::All))
+                .collect::<HashMap<_, _>>()
+        };
+
+        let notify_config = NotifyConfig {
+            engines: HashMap::from([
+                ("Stockfish".to_string(), followers(&["123", "456"])),
+                ("leela".to_string(), followers(&["123"])),
+                (ALL_ENGINES.to_string(), followers(&["789"])),
+            ]),
+            matchups: HashMap::new(),
+            openings: HashMap::from([("B43".to_string(), HashSet::from(["1011".to_string()]))]),
+            events: HashMap::new(),
+            messages: HashMap::new(),
+            ignored_engines: vec![],
+            cooldowns: HashMap::new(),
+            match_options: MatchOptions::default(),
+            warnings: vec![],
+        };
+
+        assert_eq!(
+            notify_config.summary(),
+            "Following 2 engines for 4 users\n  \
+               Every game: 1 user\n  \
+               leela: 1 user\n  \
+               Stockfish: 2 users"
+        );
+    }
+
+    #[test]
+    fn test_config_changes_apply_once_stable() {
+        let notify_config = |ignored_engines: &[&str]| NotifyConfig {
+            engines: HashMap::new(),
+            matchups: HashMap::new(),
+            openings: HashMap::new(),
+            events: HashMap::new(),
+            messages: HashMap::new(),
+            ignored_engines: ignored_engines.iter().map(|e| e.to_string()).collect(),
+            cooldowns: HashMap::new(),
+            match_options: MatchOptions::default(),
+            warnings: vec![],
+        };
+
+        let current = notify_config(&[]);
+        let mut debounce = ConfigDebounce::new(2);
+
+        assert!(debounce
+            .update(&current, notify_config(&["Test"]))
+            .is_none());
+        assert!(debounce
+            .update(&current, notify_config(&["TestEngine"]))
+            .is_none());
+        assert_eq!(
+            debounce.update(&current, notify_config(&["TestEngine"])),
+            Some(notify_config(&["TestEngine"]))
+        );
+
+        // Going back to the current config starts over
+        assert!(debounce
+            .update(&current, notify_config(&["TestEngine"]))
+            .is_none());
+        assert!(debounce.update(&current, notify_config(&[])).is_none());
+        assert!(debounce
+            .update(&current, notify_config(&["TestEngine"]))
+            .is_none());
+
+        assert_eq!(
+            ConfigDebounce::new(1).update(&current, notify_config(&["TestEngine"])),
+            Some(notify_config(&["TestEngine"]))
+        );
+    }
+
+    #[test]
+    fn test_engine_messages_must_be_valid_templates() {
+        let config = |message: &str| {
+            format!(r#"{{ "users": {{}}, "messages": {{ "Stockfish": "{message}" }} }}"#)
+        };
+
+        let parse = |config: &str| {
+            build_notify_config(&parse_config_file(config, ConfigFormat::Json5).unwrap())
+        };
+
+        let notify_config = parse(&config(":fire: {white} vs {black}")).unwrap();
+        assert!(notify_config.messages.contains_key("Stockfish"));
+
+        assert!(parse(&config("{whtie} vs {black}")).is_err());
+    }
+
+    #[test]
+    fn test_config_fetch_retries_after_server_error() {
+        let server = MockServer::start(vec![
+            MockResponse::new(503, ""),
+            MockResponse::new(200, r#"{ "users": {} }"#),
+        ]);
+        let config_url = Url::parse(server.url()).unwrap();
+        let client = ReqwestClient::new(DEFAULT_HTTP_TIMEOUT, DEFAULT_USER_AGENT).unwrap();
+
+        let (contents, _) = backoff::retry_if(
+            CONFIG_FETCH_ATTEMPTS,
+            Backoff::new(Duration::ZERO, Duration::ZERO),
+            FetchError::is_transient,
+            || fetch_config_file(&client, &config_url, None),
+        )
+        .unwrap();
+
+        assert_eq!(contents, r#"{ "users": {} }"#);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_config_fetch_doesnt_retry_rejections() {
+        let config_url = Url::parse("https://example.com/config.json5").unwrap();
+        let source = ConfigSource::Url {
+            url: config_url.clone(),
+            auth: None,
+        };
+
+        // Each client only has one response, so retrying would panic
+        let not_found = FakeHttpClient::new(vec![Err(HttpStatusError {
+            url: config_url.to_string(),
+            status: 404,
+        }
+        .into())]);
+        let html = FakeHttpClient::new(vec![Ok(HttpResponse {
+            status: 200,
+            body: "<!DOCTYPE html><title>Sign in</title>".to_string(),
+            ..Default::default()
+        })]);
+
+        for client in [not_found, html] {
+            assert!(matches!(
+                load_notify_config(&source, &client),
+                Err(NotifyConfigError::Invalid(_))
+            ));
+            assert_eq!(client.requests().len(), 1);
+        }
+    }
+
+    #[test]
+    fn test_config_format_follows_content_type() {
+        let client = FakeHttpClient::new(vec![Ok(HttpResponse {
+            status: 200,
+            body: "[users]\n\"123\" = [\"Stockfish\"]\n".to_string(),
+            content_type: Some("application/toml".to_string()),
+            ..Default::default()
+        })]);
+        let source = ConfigSource::Url {
+            url: Url::parse("https://example.com/config").unwrap(),
+            auth: None,
+        };
+
+        let notify_config = load_notify_config(&source, &client).unwrap();
+
+        assert!(notify_config.engines.contains_key("Stockfish"));
+        assert_eq!(client.requests()[0].0, "https://example.com/config");
+    }
+
+    #[test]
+    fn test_config_fetch_sends_auth() {
+        let config_url = Url::parse("https://example.com/config.json5").unwrap();
+        let client = FakeHttpClient::new(vec![Ok(HttpResponse {
+            status: 200,
+            body: r#"{ "users": {} }"#.to_string(),
+            ..Default::default()
+        })]);
+
+        fetch_config_file(&client, &config_url, Some("Bearer secret")).unwrap();
+
+        assert_eq!(
+            client.requests()[0].1,
+            vec![(AUTHORIZATION, "Bearer secret".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_config_fetch_explains_html_and_redirects() {
+        let config_url = Url::parse("https://example.com/config.json5").unwrap();
+        let client = FakeHttpClient::new(vec![
+            Ok(HttpResponse {
+                status: 200,
+                body: "<!DOCTYPE html><title>Just a moment...</title>".to_string(),
+                ..Default::default()
+            }),
+            Ok(HttpResponse {
+                status: 302,
+                location: Some("https://example.com/login".to_string()),
+                ..Default::default()
+            }),
+        ]);
+
+        let html = fetch_config_file(&client, &config_url, None).unwrap_err();
+        assert!(format!("{html:?}").contains("returned HTML"));
+
+        let redirect = fetch_config_file(&client, &config_url, None).unwrap_err();
+        assert!(format!("{redirect:?}").contains("redirected (302) to https://example.com/login"));
+    }
+
+    #[test]
+    fn test_quiet_hours_across_midnight() {
+        let overnight = QuietHours::parse("22:00-07:30").unwrap();
+        assert!(overnight.contains(23 * 60));
+        assert!(overnight.contains(0));
+        assert!(overnight.contains(7 * 60 + 29));
+        assert!(!overnight.contains(7 * 60 + 30));
+        assert!(!overnight.contains(12 * 60));
+
+        let daytime = QuietHours::parse("09:00-17:00").unwrap();
+        assert!(daytime.contains(12 * 60));
+        assert!(!daytime.contains(8 * 60));
+        assert!(!daytime.contains(17 * 60));
+
+        assert_eq!(QuietHours::parse("25:00-07:00"), None);
+        assert_eq!(QuietHours::parse("22:00"), None);
+    }
+}
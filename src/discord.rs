@@ -1,25 +1,510 @@
-use anyhow::Result;
+use crate::config::DiscordIdentity;
+use crate::http::{HttpClient, ReqwestClient};
+use crate::notify::{Mention, MessageTemplate, Notifier, NotifyContent, NotifyKind};
+use crate::tcec_pgn::{Eval, GameResult, Side};
+use anyhow::{bail, Result};
+use reqwest::blocking::Response;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
 use serde_json::{json, Value};
+use std::collections::HashSet;
+use std::time::Duration;
 
-pub fn send_message(webhook_url: &str, message: &str) -> Result<()> {
+const MAX_RATE_LIMIT_RETRIES: u32 = 3;
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// Discord rejects messages with more embeds than this
+const MAX_EMBEDS_PER_MESSAGE: usize = 10;
+
+const WHITE_WIN_COLOR: u32 = 0xf0f0f0;
+const BLACK_WIN_COLOR: u32 = 0x202020;
+const DRAW_COLOR: u32 = 0x808080;
+
+// Games in progress are colored by how the followed engine is doing, from gray when the game is
+// level towards green when it's winning or red when it's losing
+const EVEN_COLOR: u32 = 0x808080;
+const WINNING_COLOR: u32 = 0x2ecc71;
+const LOSING_COLOR: u32 = 0xe74c3c;
+
+// An engine this many pawns up is as good as winning, so gets the strongest color
+const MAX_COLORED_EVAL: f32 = 3.0;
+
+pub struct Embed {
+    pub title: String,
+    pub url: String,
+    pub description: Option<String>,
+    pub footer: String,
+    pub color: Option<u32>,
+}
+
+pub struct DiscordNotifier {
+    client: ReqwestClient,
+    webhook_url: String,
+    identity: DiscordIdentity,
+    template: Option<MessageTemplate>,
+}
+
+impl DiscordNotifier {
+    pub fn new(
+        client: ReqwestClient,
+        webhook_url: &str,
+        identity: DiscordIdentity,
+        template: Option<MessageTemplate>,
+    ) -> Self {
+        Self {
+            client,
+            webhook_url: webhook_url.to_string(),
+            identity,
+            template,
+        }
+    }
+
+    // Mentions only ping when they're in the message content, not the embed
+    fn message(&self, content: &NotifyContent) -> String {
+        let mentions_str = describe_mentions(content);
+
+        match content.template(&self.template) {
+            Some(template) => template.render(content, &mentions_str),
+            None if !mentions_str.is_empty() => format!("cc. {mentions_str}"),
+            None => String::new(),
+        }
+    }
+
+    // Several games share one message, so each game's mentions are labelled with its matchup
+    fn batch_message(&self, contents: &[NotifyContent]) -> String {
+        contents
+            .iter()
+            .map(|content| match content.template(&self.template) {
+                Some(_) => self.message(content),
+                None => match describe_mentions(content) {
+                    mentions_str if mentions_str.is_empty() => String::new(),
+                    mentions_str => format!(
+                        "{} vs. {}: {mentions_str}",
+                        content.white_player, content.black_player
+                    ),
+                },
+            })
+            .filter(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Notifier for DiscordNotifier {
+    fn name(&self) -> &'static str {
+        "Discord"
+    }
+
+    fn send(&self, content: &NotifyContent) -> Result<()> {
+        send_embeds(
+            &self.client,
+            &self.webhook_url,
+            &self.identity,
+            &self.message(content),
+            &[embed(content)],
+            &content.mentions,
+        )
+    }
+
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()> {
+        for chunk in contents.chunks(MAX_EMBEDS_PER_MESSAGE) {
+            send_embeds(
+                &self.client,
+                &self.webhook_url,
+                &self.identity,
+                &self.batch_message(chunk),
+                &chunk.iter().map(embed).collect::<Vec<_>>(),
+                &chunk
+                    .iter()
+                    .flat_map(|content| content.mentions.iter().cloned())
+                    .collect(),
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+fn describe_mentions(content: &NotifyContent) -> String {
+    content
+        .mentions
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn embed(content: &NotifyContent) -> Embed {
+    let color = match content.kind {
+        NotifyKind::Started
+        | NotifyKind::EvalSwing(_)
+        | NotifyKind::TimeTrouble(..)
+        | NotifyKind::Opening(_)
+        | NotifyKind::EngineEntered(_) => Some(eval_color(content.eval, content.followed_side)),
+        NotifyKind::Finished(result) => result_color(result),
+    };
+
+    Embed {
+        title: content.title(),
+        url: content.url.clone(),
+        description: content.description(),
+        footer: content.footer(),
+        color,
+    }
+}
+
+fn result_color(result: GameResult) -> Option<u32> {
+    match result {
+        GameResult::WhiteWin => Some(WHITE_WIN_COLOR),
+        GameResult::BlackWin => Some(BLACK_WIN_COLOR),
+        GameResult::Draw => Some(DRAW_COLOR),
+        GameResult::Ongoing => None,
+    }
+}
+
+// Without an eval, or a side to see it from, the game is shown as level
+fn eval_color(eval: Option<Eval>, followed_side: Option<Side>) -> u32 {
+    let (Some(eval), Some(followed_side)) = (eval, followed_side) else {
+        return EVEN_COLOR;
+    };
+
+    let eval = match followed_side {
+        Side::White => eval.pawns(),
+        Side::Black => -eval.pawns(),
+    }
+    .clamp(-MAX_COLORED_EVAL, MAX_COLORED_EVAL);
+
+    let target = if eval > 0.0 {
+        WINNING_COLOR
+    } else {
+        LOSING_COLOR
+    };
+
+    blend_colors(EVEN_COLOR, target, eval.abs() / MAX_COLORED_EVAL)
+}
+
+// Mix each of the red, green and blue channels, where an amount of 0 is all `from`
+fn blend_colors(from: u32, to: u32, amount: f32) -> u32 {
+    [16, 8, 0]
+        .into_iter()
+        .map(|shift| {
+            let from = ((from >> shift) & 0xff) as f32;
+            let to = ((to >> shift) & 0xff) as f32;
+            let channel = (from + (to - from) * amount).round() as u32;
+
+            channel << shift
+        })
+        .sum()
+}
+
+pub fn send_message(
+    client: &ReqwestClient,
+    webhook_url: &str,
+    identity: &DiscordIdentity,
+    message: &str,
+    mentions: &HashSet<Mention>,
+) -> Result<()> {
     call_webhook(
+        client,
         webhook_url,
-        json!({
-            "username": "tcec-notifier",
-            "allowed_mentions": { "parse": ["users"] },
-            "content": message
-        }),
+        message_body(message, identity, mentions),
     )
 }
 
-fn call_webhook(webhook_url: &str, body: Value) -> Result<()> {
-    let client = reqwest::blocking::Client::new();
+pub fn send_embeds(
+    client: &ReqwestClient,
+    webhook_url: &str,
+    identity: &DiscordIdentity,
+    message: &str,
+    embeds: &[Embed],
+    mentions: &HashSet<Mention>,
+) -> Result<()> {
+    let embeds = embeds.iter().map(embed_body).collect::<Vec<_>>();
+
+    let mut body = message_body(message, identity, mentions);
+    body["embeds"] = json!(embeds);
+
+    call_webhook(client, webhook_url, body)
+}
+
+fn embed_body(embed: &Embed) -> Value {
+    let mut body = json!({
+        "title": embed.title,
+        "url": embed.url,
+        "footer": { "text": embed.footer }
+    });
+
+    if let Some(description) = &embed.description {
+        body["description"] = json!(description);
+    }
+
+    if let Some(color) = embed.color {
+        body["color"] = json!(color);
+    }
+
+    body
+}
+
+fn message_body(message: &str, identity: &DiscordIdentity, mentions: &HashSet<Mention>) -> Value {
+    // Only the users and roles being notified can be pinged, rather than anything in the message
+    // which looks like a mention
+    let mut mentions = mentions.iter().collect::<Vec<_>>();
+    mentions.sort();
+
+    let ids = |roles: bool| {
+        mentions
+            .iter()
+            .filter(|mention| mention.is_role() == roles)
+            .map(|mention| mention.id())
+            .collect::<Vec<_>>()
+    };
+
+    let mut body = json!({
+        "username": identity.username,
+        "allowed_mentions": { "parse": [], "users": ids(false), "roles": ids(true) },
+        "content": message
+    });
+
+    if let Some(avatar_url) = &identity.avatar_url {
+        body["avatar_url"] = json!(avatar_url);
+    }
+
+    body
+}
+
+/// Check that a webhook exists, without posting to it. Discord answers a GET on a webhook with
+/// its details.
+pub fn check_webhook(client: &dyn HttpClient, webhook_url: &str) -> Result<()> {
+    let response = client.get(webhook_url, &[])?;
+
+    let is_webhook =
+        serde_json::from_str::<Value>(&response.body).is_ok_and(|body| body.get("id").is_some());
 
-    client
-        .post(webhook_url)
-        .json(&body)
-        .send()?
-        .error_for_status()?;
+    if !is_webhook {
+        bail!(
+            "Response doesn't look like a Discord webhook ({})",
+            response.status
+        );
+    }
 
     Ok(())
 }
+
+fn call_webhook(client: &ReqwestClient, webhook_url: &str, body: Value) -> Result<()> {
+    let mut retries = 0;
+
+    loop {
+        let response = client.post_json(webhook_url, &body)?;
+
+        if response.status() != StatusCode::TOO_MANY_REQUESTS || retries >= MAX_RATE_LIMIT_RETRIES {
+            response.error_for_status()?;
+            return Ok(());
+        }
+
+        retries += 1;
+        std::thread::sleep(get_retry_after(response));
+    }
+}
+
+// Discord reports how long to wait both in the `Retry-After` header and in the body
+fn get_retry_after(response: Response) -> Duration {
+    let header_retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<f64>().ok());
+
+    let retry_after = header_retry_after.or_else(|| {
+        response
+            .json::<Value>()
+            .ok()
+            .and_then(|body| body.get("retry_after")?.as_f64())
+    });
+
+    retry_after
+        .filter(|seconds| seconds.is_finite() && *seconds >= 0.0)
+        .map(Duration::from_secs_f64)
+        .unwrap_or(DEFAULT_RETRY_AFTER)
+        .min(MAX_RETRY_AFTER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{FakeHttpClient, HttpResponse};
+    use crate::mock_server::{MockResponse, MockServer};
+    use crate::tcec::{EngineName, TCEC_URL};
+
+    fn content(white: &str, black: &str, mentions: &[&str]) -> NotifyContent {
+        NotifyContent {
+            kind: NotifyKind::Started,
+            white_player: EngineName::new(white),
+            black_player: EngineName::new(black),
+            white_elo: None,
+            black_elo: None,
+            tournament: "TCEC Season 29 - Category 1 Playoff".to_string(),
+            round: None,
+            url: TCEC_URL.to_string(),
+            eco: None,
+            opening: None,
+            variation: None,
+            time_control: None,
+            book_length: None,
+            eval: None,
+            followed_side: None,
+            mentions: mentions
+                .iter()
+                .map(|id| Mention::parse(id))
+                .collect::<HashSet<_>>(),
+            engine_template: None,
+        }
+    }
+
+    #[test]
+    fn test_call_webhook_retries_after_rate_limit() {
+        let server = MockServer::start(vec![
+            MockResponse::new(429, r#"{"retry_after": 0}"#).with_header("Retry-After", "0"),
+            MockResponse::new(204, ""),
+        ]);
+
+        send_message(
+            &ReqwestClient::for_tests(),
+            server.url(),
+            &DiscordIdentity::default(),
+            "hello",
+            &HashSet::new(),
+        )
+        .unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_call_webhook_gives_up_when_always_rate_limited() {
+        let server = MockServer::start(
+            (0..=MAX_RATE_LIMIT_RETRIES)
+                .map(|_| MockResponse::new(429, "").with_header("Retry-After", "0"))
+                .collect(),
+        );
+
+        assert!(send_message(
+            &ReqwestClient::for_tests(),
+            server.url(),
+            &DiscordIdentity::default(),
+            "hello",
+            &HashSet::new()
+        )
+        .is_err());
+        assert_eq!(server.requests().len(), MAX_RATE_LIMIT_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn test_check_webhook_expects_webhook_details() {
+        let client = FakeHttpClient::new(vec![
+            Ok(HttpResponse {
+                status: 200,
+                body: r#"{"type": 1, "id": "123", "name": "tcec-notifier"}"#.to_string(),
+                ..Default::default()
+            }),
+            Ok(HttpResponse {
+                status: 200,
+                body: "<html></html>".to_string(),
+                ..Default::default()
+            }),
+        ]);
+
+        assert!(check_webhook(&client, "https://discord.com/api/webhooks/123/abc").is_ok());
+        assert!(check_webhook(&client, "https://discord.com/api/webhooks/123/abc").is_err());
+    }
+
+    #[test]
+    fn test_message_body_only_allows_notified_mentions() {
+        let mentions = ["5678", "role:90", "1234"]
+            .iter()
+            .map(|id| Mention::parse(id))
+            .collect::<HashSet<_>>();
+
+        let body = message_body(
+            "<@!1234> <@!5678> <@!666> <@&90>",
+            &DiscordIdentity::default(),
+            &mentions,
+        );
+        assert_eq!(
+            body["allowed_mentions"],
+            json!({ "parse": [], "users": ["1234", "5678"], "roles": ["90"] })
+        );
+
+        let body = message_body("<@!666>", &DiscordIdentity::default(), &HashSet::new());
+        assert_eq!(
+            body["allowed_mentions"],
+            json!({ "parse": [], "users": [], "roles": [] })
+        );
+    }
+
+    #[test]
+    fn test_message_body_uses_identity() {
+        let default_body = message_body("hello", &DiscordIdentity::default(), &HashSet::new());
+        assert_eq!(default_body["username"], json!("tcec-notifier"));
+        assert!(default_body.get("avatar_url").is_none());
+
+        let identity = DiscordIdentity {
+            username: "Chess Bot".to_string(),
+            avatar_url: Some("https://example.com/avatar.png".to_string()),
+        };
+        let body = message_body("hello", &identity, &HashSet::new());
+        assert_eq!(body["username"], json!("Chess Bot"));
+        assert_eq!(body["avatar_url"], json!("https://example.com/avatar.png"));
+    }
+
+    #[test]
+    fn test_eval_color_follows_the_followed_side() {
+        assert_eq!(eval_color(None, Some(Side::White)), EVEN_COLOR);
+        assert_eq!(eval_color(Some(Eval::Cp(1.5)), None), EVEN_COLOR);
+        assert_eq!(
+            eval_color(Some(Eval::Cp(0.0)), Some(Side::White)),
+            EVEN_COLOR
+        );
+
+        assert_eq!(
+            eval_color(Some(Eval::Cp(3.0)), Some(Side::White)),
+            WINNING_COLOR
+        );
+        assert_eq!(
+            eval_color(Some(Eval::Cp(3.0)), Some(Side::Black)),
+            LOSING_COLOR
+        );
+        assert_eq!(
+            eval_color(Some(Eval::Cp(-12.0)), Some(Side::Black)),
+            WINNING_COLOR
+        );
+        assert_eq!(
+            eval_color(Some(Eval::Mate(-4)), Some(Side::Black)),
+            WINNING_COLOR
+        );
+
+        // Halfway between gray and green
+        assert_eq!(eval_color(Some(Eval::Cp(1.5)), Some(Side::White)), 0x57a679);
+    }
+
+    #[test]
+    fn test_batch_message_labels_mentions_with_matchup() {
+        let notifier = DiscordNotifier::new(
+            ReqwestClient::for_tests(),
+            "https://example.com",
+            DiscordIdentity::default(),
+            None,
+        );
+
+        let contents = [
+            content("c4ke 1.1", "Minic 3.44", &["1234"]),
+            content("Stockfish", "Leela", &[]),
+            content("Berserk", "Caissa", &["role:5678"]),
+        ];
+
+        assert_eq!(
+            notifier.batch_message(&contents),
+            "c4ke 1.1 vs. Minic 3.44: <@!1234>\nBerserk vs. Caissa: <@&5678>"
+        );
+    }
+}
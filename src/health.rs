@@ -0,0 +1,105 @@
+//! An optional HTTP endpoint reporting whether the poll loop is healthy, for use as a liveness
+//! probe. The standings built up over the run are served alongside it at `/standings`.
+
+use crate::standings::SharedStandings;
+use anyhow::{anyhow, Result};
+use serde_json::json;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tiny_http::{Header, Response, Server};
+
+#[derive(Debug, Default)]
+pub struct Health {
+    last_success: Option<SystemTime>,
+    last_error: Option<String>,
+}
+
+impl Health {
+    pub fn record_success(&mut self) {
+        self.last_success = Some(SystemTime::now());
+    }
+
+    pub fn record_error(&mut self, error: String) {
+        self.last_error = Some(error);
+    }
+
+    fn to_json(&self) -> String {
+        let last_success = self
+            .last_success
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs());
+
+        json!({
+            "last_success": last_success,
+            "last_error": self.last_error,
+        })
+        .to_string()
+    }
+}
+
+pub type SharedHealth = Arc<Mutex<Health>>;
+
+/// Serve the health of the poll loop on a background thread
+pub fn start(port: u16, health: SharedHealth, standings: SharedStandings) -> Result<()> {
+    let server = Server::http(("0.0.0.0", port))
+        .map_err(|e| anyhow!("Unable to listen on port {port}: {e}"))?;
+
+    std::thread::spawn(move || serve(server, health, standings));
+
+    Ok(())
+}
+
+fn serve(server: Server, health: SharedHealth, standings: SharedStandings) {
+    for request in server.incoming_requests() {
+        let body = match request.url() {
+            "/standings" => standings.lock().unwrap().to_json(),
+            _ => health.lock().unwrap().to_json(),
+        };
+
+        let response = Response::from_string(body).with_header(
+            Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap(),
+        );
+
+        // The client may have gone away, which shouldn't stop us serving anyone else
+        let _ = request.respond(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::StdoutLogger;
+    use crate::standings::Standings;
+
+    #[test]
+    fn test_serves_health_as_json() {
+        let health = SharedHealth::default();
+        health
+            .lock()
+            .unwrap()
+            .record_error("Unable to fetch".to_string());
+
+        let server = Server::http("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/", server.server_addr().to_ip().unwrap());
+        // Nothing is recorded, so the standings file is never written
+        let standings_path = std::env::temp_dir().join(format!(
+            "tcec-notifier-test-health-standings-{}.txt",
+            std::process::id()
+        ));
+        let standings = Arc::new(Mutex::new(
+            Standings::load_from(&standings_path, &StdoutLogger).unwrap(),
+        ));
+        std::thread::spawn({
+            let health = health.clone();
+            move || serve(server, health, standings)
+        });
+
+        let response = reqwest::blocking::Client::new().get(url).send().unwrap();
+
+        assert_eq!(response.status(), reqwest::StatusCode::OK);
+        assert_eq!(
+            response.text().unwrap(),
+            r#"{"last_error":"Unable to fetch","last_success":null}"#
+        );
+    }
+}
@@ -0,0 +1,163 @@
+//! Fetching over HTTP, behind a trait so tests can supply canned responses instead of a server.
+
+use anyhow::Result;
+use reqwest::blocking::Response;
+use reqwest::header::{HeaderName, CONTENT_TYPE, ETAG, LAST_MODIFIED, LOCATION};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Sent with every request unless overridden, so TCEC can tell where the traffic comes from
+pub const DEFAULT_USER_AGENT: &str = concat!("tcec-notifier/", env!("CARGO_PKG_VERSION"));
+
+/// A successful response, along with the few headers we make use of
+#[derive(Debug, Clone, Default)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub body: String,
+    pub content_type: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+
+    /// Where we were redirected to, as redirects aren't followed
+    pub location: Option<String>,
+}
+
+/// An error status in reply to a request, kept apart from the request not getting through
+#[derive(Debug)]
+pub struct HttpStatusError {
+    pub url: String,
+    pub status: u16,
+}
+
+impl HttpStatusError {
+    /// Whether the status says the request was wrong, so repeating it won't help
+    pub fn is_client_error(&self) -> bool {
+        (400..500).contains(&self.status)
+    }
+}
+
+impl std::fmt::Display for HttpStatusError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HTTP status {} for {}", self.status, self.url)
+    }
+}
+
+impl std::error::Error for HttpStatusError {}
+
+pub trait HttpClient {
+    /// Make a GET request with some extra headers. Error statuses are returned as an
+    /// `HttpStatusError`.
+    fn get(&self, url: &str, headers: &[(HeaderName, &str)]) -> Result<HttpResponse>;
+}
+
+/// A client which is built once and shared, so connections are reused between requests
+#[derive(Clone)]
+pub struct ReqwestClient {
+    client: reqwest::blocking::Client,
+}
+
+impl ReqwestClient {
+    pub fn new(timeout: Duration, user_agent: &str) -> Result<Self> {
+        let client = reqwest::blocking::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .timeout(timeout)
+            .user_agent(user_agent)
+            .build()?;
+
+        Ok(Self { client })
+    }
+
+    /// Make a POST request with a JSON body. Unlike `get`, error statuses are left to the caller.
+    pub fn post_json(&self, url: &str, body: &Value) -> Result<Response> {
+        Ok(self.client.post(url).json(body).send()?)
+    }
+}
+
+#[cfg(test)]
+impl ReqwestClient {
+    pub fn for_tests() -> Self {
+        Self::new(Duration::from_secs(5), DEFAULT_USER_AGENT).unwrap()
+    }
+}
+
+impl HttpClient for ReqwestClient {
+    fn get(&self, url: &str, headers: &[(HeaderName, &str)]) -> Result<HttpResponse> {
+        let mut request = self.client.get(url);
+
+        for (name, value) in headers {
+            request = request.header(name.clone(), *value);
+        }
+
+        let response = request.send()?;
+
+        let status = response.status().as_u16();
+        if status >= 400 {
+            return Err(HttpStatusError {
+                url: url.to_string(),
+                status,
+            }
+            .into());
+        }
+
+        Ok(HttpResponse {
+            status,
+            content_type: get_header(&response, CONTENT_TYPE),
+            etag: get_header(&response, ETAG),
+            last_modified: get_header(&response, LAST_MODIFIED),
+            location: get_header(&response, LOCATION),
+            body: response.text()?,
+        })
+    }
+}
+
+fn get_header(response: &Response, name: HeaderName) -> Option<String> {
+    response
+        .headers()
+        .get(name)
+        .and_then(|value| value.to_str().ok())
+        .map(|value| value.to_string())
+}
+
+/// The URL and extra headers of a request made to a `FakeHttpClient`
+#[cfg(test)]
+pub type RecordedRequest = (String, Vec<(HeaderName, String)>);
+
+/// Replies to each request with the next of a list of canned responses, and records the
+/// requests it was sent
+#[cfg(test)]
+pub struct FakeHttpClient {
+    responses: std::cell::RefCell<std::collections::VecDeque<Result<HttpResponse>>>,
+    requests: std::cell::RefCell<Vec<RecordedRequest>>,
+}
+
+#[cfg(test)]
+impl FakeHttpClient {
+    pub fn new(responses: Vec<Result<HttpResponse>>) -> Self {
+        Self {
+            responses: std::cell::RefCell::new(responses.into()),
+            requests: std::cell::RefCell::new(vec![]),
+        }
+    }
+
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.borrow().clone()
+    }
+}
+
+#[cfg(test)]
+impl HttpClient for FakeHttpClient {
+    fn get(&self, url: &str, headers: &[(HeaderName, &str)]) -> Result<HttpResponse> {
+        self.requests.borrow_mut().push((
+            url.to_string(),
+            headers
+                .iter()
+                .map(|(name, value)| (name.clone(), value.to_string()))
+                .collect(),
+        ));
+
+        self.responses
+            .borrow_mut()
+            .pop_front()
+            .expect("No responses left for fake HTTP client")
+    }
+}
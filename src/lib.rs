@@ -0,0 +1,1153 @@
+//! Notifies people when the engines they follow start playing on TCEC.
+//!
+//! The `tcec-notifier` binary configures everything from the environment, but the polling
+//! logic can be driven directly with [`poll_once`].
+
+use crate::config::{Config, ALL_ENGINES};
+use crate::http::HttpClient;
+use crate::log::Logger;
+use crate::notify::{Mention, MessageTemplate, NotifyContent, NotifyKind};
+use crate::standings::SharedStandings;
+use crate::state::{EngineCooldowns, NotifiedResults, SeenEngines, SeenGames};
+use crate::tcec::PgnCache;
+use crate::tcec_pgn::Side;
+use anyhow::Result;
+use std::collections::{HashMap, HashSet};
+use std::path::Path;
+use std::time::SystemTime;
+
+mod audit;
+pub mod backoff;
+pub mod config;
+pub mod discord;
+pub mod health;
+pub mod http;
+pub mod log;
+pub mod metrics;
+#[cfg(test)]
+mod mock_server;
+pub mod notify;
+mod slack;
+pub mod standings;
+pub mod state;
+pub mod tcec;
+pub mod tcec_pgn;
+mod telegram;
+
+pub use crate::config::NotifyConfig;
+pub use crate::notify::Notifier;
+pub use crate::tcec::{CurrentGames, EngineName};
+pub use crate::tcec_pgn::Pgn;
+
+// Followers of engines which are cooling down aren't notified, but anyone following the game for
+// another reason still is. That includes users following every game, as a cooldown is for hearing
+// less about one engine and they haven't asked to hear about any engine in particular.
+fn get_mentions(
+    notify_config: &NotifyConfig,
+    game: &Pgn,
+    cooling_down: &[&EngineName],
+    log: &dyn Logger,
+) -> HashSet<Mention> {
+    let mut mentions = HashSet::new();
+
+    // Anyone following every game who also follows one of its engines is only in the set once
+    let all_engines_users = notify_config.all_engines_users(&game.event);
+
+    if !all_engines_users.is_empty() {
+        log.info(&format!(
+            "Will notify {} users following every game",
+            all_engines_users.len()
+        ));
+        mentions.extend(all_engines_users);
+    }
+
+    for (engine, users) in &notify_config.engines {
+        if engine == ALL_ENGINES {
+            continue;
+        }
+
+        if cooling_down
+            .iter()
+            .any(|player| player.matches(engine, &notify_config.match_options))
+        {
+            log.info(&format!("Not notifying for `{engine}`, it's cooling down"));
+            continue;
+        }
+
+        if game.has_player(engine, &notify_config.match_options) {
+            if !game.has_player(engine, &notify_config.match_options.without_fuzzy()) {
+                log.warning(&format!(
+                    "`{engine}` only fuzzily matched `{}` vs `{}`, is there a typo in the config?",
+                    game.white_player, game.black_player,
+                ));
+            }
+
+            let notifies = users
+                .iter()
+                .filter(|(_, events)| events.matches(&game.event))
+                .map(|(user, _)| Mention::parse(user))
+                .collect::<Vec<_>>();
+
+            log.info(&format!(
+                "Will notify {} users for engine `{}`",
+                notifies.len(),
+                &engine,
+            ));
+            mentions.extend(notifies);
+        }
+    }
+
+    // Someone following a matchup and one of its engines is only in the set once
+    for ([player, other], users) in &notify_config.matchups {
+        if game.is_between(player, other, &notify_config.match_options) {
+            log.info(&format!(
+                "Will notify {} users for matchup `{}` vs `{}`",
+                users.len(),
+                player,
+                other,
+            ));
+            mentions.extend(users.iter().map(|user| Mention::parse(user)));
+        }
+    }
+
+    // Users following the whole tournament are notified whoever is playing
+    let event_users = notify_config
+        .events
+        .iter()
+        .filter(|(_, events)| events.matches(&game.event))
+        .map(|(user, _)| Mention::parse(user))
+        .collect::<Vec<_>>();
+
+    if !event_users.is_empty() {
+        log.info(&format!(
+            "Will notify {} users for event `{}`",
+            event_users.len(),
+            game.event
+        ));
+        mentions.extend(event_users);
+    }
+
+    mentions
+}
+
+// The users following a single engine in a game, rather than either of its players
+fn get_engine_mentions(
+    notify_config: &NotifyConfig,
+    engine: &EngineName,
+    event: &str,
+) -> HashSet<Mention> {
+    notify_config
+        .engines
+        .iter()
+        .filter(|(followed, _)| engine.matches(followed, &notify_config.match_options))
+        .flat_map(|(_, users)| users.iter())
+        .filter(|(_, events)| events.matches(event))
+        .map(|(user, _)| Mention::parse(user))
+        .collect()
+}
+
+// White's message is used if both engines have one, so the choice doesn't depend on map order
+fn get_engine_template(notify_config: &NotifyConfig, game: &Pgn) -> Option<MessageTemplate> {
+    [&game.white_player, &game.black_player]
+        .into_iter()
+        .find_map(|player| {
+            notify_config
+                .messages
+                .iter()
+                .filter(|(engine, _)| player.matches(engine, &notify_config.match_options))
+                .min_by_key(|(engine, _)| *engine)
+                .map(|(_, template)| template.clone())
+        })
+}
+
+// The side whose engine is followed, if only one of them is
+fn get_followed_side(notify_config: &NotifyConfig, game: &Pgn) -> Option<Side> {
+    let is_followed = |engine| !get_engine_mentions(notify_config, engine, &game.event).is_empty();
+
+    match (
+        is_followed(&game.white_player),
+        is_followed(&game.black_player),
+    ) {
+        (true, false) => Some(Side::White),
+        (false, true) => Some(Side::Black),
+        _ => None,
+    }
+}
+
+fn new_content(
+    config: &Config,
+    notify_config: &NotifyConfig,
+    kind: NotifyKind,
+    game: &Pgn,
+    mentions: HashSet<Mention>,
+) -> NotifyContent {
+    NotifyContent::new(kind, game, &config.site_url, mentions)
+        .with_engine_template(get_engine_template(notify_config, game))
+        .with_followed_side(get_followed_side(notify_config, game))
+}
+
+fn started_content(
+    config: &Config,
+    notify_config: &NotifyConfig,
+    game: &Pgn,
+    cooling_down: &[&EngineName],
+    log: &dyn Logger,
+) -> NotifyContent {
+    log.info(&format!(
+        "`{}` vs `{}`",
+        game.white_player, game.black_player,
+    ));
+
+    new_content(
+        config,
+        notify_config,
+        NotifyKind::Started,
+        game,
+        get_mentions(notify_config, game, cooling_down, log),
+    )
+}
+
+fn notify_eval_swing(
+    config: &Config,
+    notifier: &dyn Notifier,
+    notify_config: &NotifyConfig,
+    game: &Pgn,
+    notified_swings: &mut HashMap<u64, usize>,
+    log: &dyn Logger,
+) {
+    let (Some(threshold), Some(swing)) = (config.eval_swing_threshold, game.max_eval_swing())
+    else {
+        return;
+    };
+
+    // The largest swing stays the same until a bigger one comes along, so only notify for it once
+    if swing.swing < threshold || notified_swings.get(&game.as_hash()) == Some(&swing.ply) {
+        return;
+    }
+
+    notified_swings.insert(game.as_hash(), swing.ply);
+
+    let mentions = get_mentions(notify_config, game, &[], log);
+    if mentions.is_empty() {
+        return;
+    }
+
+    log.info(&format!(
+        "`{}` vs `{}` eval swung by {:.2}",
+        game.white_player, game.black_player, swing.swing
+    ));
+
+    let notify_result = notify::notify(
+        config,
+        notifier,
+        new_content(
+            config,
+            notify_config,
+            NotifyKind::EvalSwing(swing.swing),
+            game,
+            mentions,
+        ),
+        log,
+    );
+
+    if let Err(e) = notify_result {
+        log.error(&format!("Unable to send eval swing notify: {:?}", e));
+    }
+}
+
+fn notify_time_trouble(
+    config: &Config,
+    notifier: &dyn Notifier,
+    notify_config: &NotifyConfig,
+    game: &Pgn,
+    notified_time_trouble: &mut HashSet<(u64, String)>,
+    log: &dyn Logger,
+) {
+    let Some(threshold) = config.time_trouble_threshold else {
+        return;
+    };
+
+    let clocks = game.clocks();
+
+    for (engine, clock) in [
+        (&game.white_player, clocks.white),
+        (&game.black_player, clocks.black),
+    ] {
+        let Some(remaining) = clock.filter(|remaining| *remaining < threshold) else {
+            continue;
+        };
+
+        // The clock stays low for the rest of the game, so only notify the first time it drops
+        if !notified_time_trouble.insert((game.as_hash(), engine.to_string())) {
+            continue;
+        }
+
+        let mentions = get_engine_mentions(notify_config, engine, &game.event);
+        if mentions.is_empty() {
+            continue;
+        }
+
+        log.info(&format!(
+            "`{engine}` is in time trouble with {}s left, will notify {} users",
+            remaining.as_secs(),
+            mentions.len()
+        ));
+
+        let notify_result = notify::notify(
+            config,
+            notifier,
+            new_content(
+                config,
+                notify_config,
+                NotifyKind::TimeTrouble(engine.clone(), remaining),
+                game,
+                mentions,
+            ),
+            log,
+        );
+
+        if let Err(e) = notify_result {
+            log.error(&format!("Unable to send time trouble notify: {:?}", e));
+        }
+    }
+}
+
+// A game to notify has started, along with the engines whose cooldown starts once it has been
+struct StartedGame {
+    content: NotifyContent,
+    cooldowns: Vec<EngineName>,
+}
+
+/// What we know about the games we've seen, across polls
+pub struct GameTracker {
+    seen_games: SeenGames,
+
+    // The engines we've seen play in each event
+    seen_engines: SeenEngines,
+
+    // Games whose result we've already dealt with, so a restart doesn't announce them again
+    notified_results: NotifiedResults,
+
+    // Games we've seen in progress, so we can tell when they finish
+    in_progress_games: HashSet<u64>,
+
+    // The ply of the last eval swing we notified for in each game
+    notified_swings: HashMap<u64, usize>,
+
+    // The engines we've notified are in time trouble in each game
+    notified_time_trouble: HashSet<(u64, String)>,
+
+    // Results of the games we've seen finish
+    standings: SharedStandings,
+
+    // When we last notified about each engine with a cooldown starting a game
+    engine_cooldowns: EngineCooldowns,
+
+    // Games which started during this poll, which are notified for together at the end of it
+    started_games: Vec<StartedGame>,
+
+    // Engines which played their first game in an event during this poll, which are notified for
+    // after the games themselves
+    entered_engines: Vec<NotifyContent>,
+
+    // Engines already playing when we start may have been in their event for a while, so on the
+    // first poll they're only recorded
+    first_poll: bool,
+}
+
+impl GameTracker {
+    pub fn load(state_path: &Path, standings: SharedStandings, log: &dyn Logger) -> Result<Self> {
+        Ok(Self {
+            seen_games: SeenGames::load(state_path, log)?,
+            seen_engines: SeenEngines::load(state_path, log)?,
+            notified_results: NotifiedResults::load(state_path, log)?,
+            engine_cooldowns: EngineCooldowns::load(state_path, log)?,
+            in_progress_games: HashSet::new(),
+            notified_swings: HashMap::new(),
+            notified_time_trouble: HashSet::new(),
+            standings,
+            started_games: vec![],
+            entered_engines: vec![],
+            first_poll: true,
+        })
+    }
+
+    /// A tracker which doesn't read or write any state files, so forgets everything on exit
+    pub fn in_memory(standings: SharedStandings) -> Self {
+        Self {
+            seen_games: SeenGames::in_memory(),
+            seen_engines: SeenEngines::in_memory(),
+            notified_results: NotifiedResults::in_memory(),
+            engine_cooldowns: EngineCooldowns::in_memory(),
+            in_progress_games: HashSet::new(),
+            notified_swings: HashMap::new(),
+            notified_time_trouble: HashSet::new(),
+            standings,
+            started_games: vec![],
+            entered_engines: vec![],
+            first_poll: true,
+        }
+    }
+
+    /// Make sure everything we've seen is on disk, before shutting down
+    pub fn sync(&self, log: &dyn Logger) {
+        if let Err(e) = self.seen_games.sync() {
+            log.error(&format!("Unable to sync state file: {:?}", e));
+        }
+
+        if let Err(e) = self.seen_engines.sync() {
+            log.error(&format!("Unable to sync engines file: {:?}", e));
+        }
+
+        if let Err(e) = self.notified_results.sync() {
+            log.error(&format!("Unable to sync results file: {:?}", e));
+        }
+
+        if let Err(e) = self.engine_cooldowns.sync() {
+            log.error(&format!("Unable to sync cooldowns file: {:?}", e));
+        }
+    }
+
+    fn handle_game(
+        &mut self,
+        config: &Config,
+        notifier: &dyn Notifier,
+        notify_config: &NotifyConfig,
+        game: &Pgn,
+        log: &dyn Logger,
+    ) {
+        // Ignored games are still recorded, so they aren't new if the engine is unignored
+        if notify_config.ignores(game) {
+            if game.out_of_book() && !self.seen_games.contains(game) {
+                if let Err(e) = self.seen_games.add(game) {
+                    log.error(&format!("Unable to write seen game to file: {:?}", e));
+                }
+            }
+
+            return;
+        }
+
+        self.notify_opening(config, notifier, notify_config, game, log);
+
+        if !game.out_of_book() {
+            // We only get games still in book if we've been asked to notify for them
+            if !self.seen_games.contains_pairing(game) {
+                let started_game = self.started_game(config, notify_config, game, log);
+                self.started_games.push(started_game);
+
+                if let Err(e) = self.seen_games.add_pairing(game) {
+                    log.error(&format!("Unable to write seen game to file: {:?}", e));
+                }
+            }
+
+            return;
+        }
+
+        self.record_engines_entered(config, notify_config, game, log);
+
+        if self.seen_games.contains(game) {
+            let result = game.result();
+
+            if !result.is_finished() {
+                self.in_progress_games.insert(game.as_hash());
+                notify_eval_swing(
+                    config,
+                    notifier,
+                    notify_config,
+                    game,
+                    &mut self.notified_swings,
+                    log,
+                );
+                notify_time_trouble(
+                    config,
+                    notifier,
+                    notify_config,
+                    game,
+                    &mut self.notified_time_trouble,
+                    log,
+                );
+            } else if self.in_progress_games.remove(&game.as_hash())
+                && !self.notified_results.contains(game)
+            {
+                self.notified_swings.remove(&game.as_hash());
+                self.notified_time_trouble
+                    .retain(|(hash, _)| *hash != game.as_hash());
+
+                // We saw this game in progress and it's now finished
+                log.info(&format!(
+                    "`{}` vs `{}` finished: {}",
+                    game.white_player, game.black_player, result
+                ));
+
+                // Only games we saw in progress get here, and only once, so none are counted twice
+                let strip_patterns = &notify_config.match_options.strip_patterns;
+                if let Err(e) = self.standings.lock().unwrap().record(game, strip_patterns) {
+                    log.error(&format!("Unable to write standings to file: {:?}", e));
+                }
+
+                if let Err(e) = self.notified_results.add(game) {
+                    log.error(&format!("Unable to write notified result to file: {:?}", e));
+                }
+
+                if config.notify_decisive_only && !result.is_decisive() {
+                    return;
+                }
+
+                let notify_result = notify::notify(
+                    config,
+                    notifier,
+                    new_content(
+                        config,
+                        notify_config,
+                        NotifyKind::Finished(result),
+                        game,
+                        get_mentions(notify_config, game, &[], log),
+                    ),
+                    log,
+                );
+
+                if let Err(e) = notify_result {
+                    log.error(&format!("Unable to send result notify: {:?}", e));
+                }
+            }
+
+            return;
+        }
+
+        // If we got this far, we've got a new game, though we may have already notified for it
+        // while it was in book
+        if !(config.notify_in_book && self.seen_games.contains_pairing(game)) {
+            let started_game = self.started_game(config, notify_config, game, log);
+            self.started_games.push(started_game);
+        }
+
+        let write_state_result = self.seen_games.add(game);
+
+        if let Err(e) = write_state_result {
+            log.error(&format!("Unable to write seen game to file: {:?}", e));
+        }
+
+        if !game.result().is_finished() {
+            self.in_progress_games.insert(game.as_hash());
+        }
+    }
+
+    // Engines with a cooldown are only notified about if they haven't been recently. Their
+    // cooldown only starts again once their followers have been sent a notification, which isn't
+    // until the end of the poll, so games earlier in the same poll count too.
+    fn started_game(
+        &self,
+        config: &Config,
+        notify_config: &NotifyConfig,
+        game: &Pgn,
+        log: &dyn Logger,
+    ) -> StartedGame {
+        let now = SystemTime::now();
+        let strip_patterns = &notify_config.match_options.strip_patterns;
+        let mut cooling_down = vec![];
+        let mut cooldowns = vec![];
+
+        for engine in [&game.white_player, &game.black_player] {
+            let Some(cooldown) = notify_config.cooldown(engine) else {
+                continue;
+            };
+
+            let starting_this_poll = self
+                .started_games
+                .iter()
+                .flat_map(|started_game| &started_game.cooldowns)
+                .any(|other| other.normalized(strip_patterns) == engine.normalized(strip_patterns));
+
+            if starting_this_poll
+                || self
+                    .engine_cooldowns
+                    .is_cooling_down(engine, strip_patterns, cooldown, now)
+            {
+                cooling_down.push(engine);
+            } else if !get_engine_mentions(notify_config, engine, &game.event).is_empty() {
+                cooldowns.push(engine.clone());
+            }
+        }
+
+        StartedGame {
+            content: started_content(config, notify_config, game, &cooling_down, log),
+            cooldowns,
+        }
+    }
+
+    fn start_cooldowns(
+        &mut self,
+        engines: &[EngineName],
+        notify_config: &NotifyConfig,
+        log: &dyn Logger,
+    ) {
+        let now = SystemTime::now();
+        let strip_patterns = &notify_config.match_options.strip_patterns;
+
+        for engine in engines {
+            if let Err(e) = self.engine_cooldowns.record(engine, strip_patterns, now) {
+                log.error(&format!("Unable to write cooldown to file: {:?}", e));
+            }
+        }
+    }
+
+    // Openings are watched regardless of who's playing, so they're notified separately
+    fn notify_opening(
+        &mut self,
+        config: &Config,
+        notifier: &dyn Notifier,
+        notify_config: &NotifyConfig,
+        game: &Pgn,
+        log: &dyn Logger,
+    ) {
+        if self.seen_games.contains_opening(game) {
+            return;
+        }
+
+        let mut openings = vec![];
+        let mut mentions = HashSet::new();
+
+        for (opening, users) in &notify_config.openings {
+            if game.has_opening(opening) {
+                openings.push(opening.as_str());
+                mentions.extend(users.iter().map(|user| Mention::parse(user)));
+            }
+        }
+
+        if openings.is_empty() {
+            return;
+        }
+
+        openings.sort();
+        let openings = openings.join(", ");
+
+        log.info(&format!(
+            "`{}` vs `{}` is playing watched opening {}, will notify {} users",
+            game.white_player,
+            game.black_player,
+            openings,
+            mentions.len()
+        ));
+
+        if let Err(e) = self.seen_games.add_opening(game) {
+            log.error(&format!("Unable to write seen opening to file: {:?}", e));
+        }
+
+        let notify_result = notify::notify(
+            config,
+            notifier,
+            new_content(
+                config,
+                notify_config,
+                NotifyKind::Opening(openings),
+                game,
+                mentions,
+            ),
+            log,
+        );
+
+        if let Err(e) = notify_result {
+            log.error(&format!("Unable to send opening notify: {:?}", e));
+        }
+    }
+
+    // Only games which have left book count, as one still in book hasn't started yet
+    fn record_engines_entered(
+        &mut self,
+        config: &Config,
+        notify_config: &NotifyConfig,
+        game: &Pgn,
+        log: &dyn Logger,
+    ) {
+        let strip_patterns = &notify_config.match_options.strip_patterns;
+        let now = SystemTime::now();
+
+        for engine in [&game.white_player, &game.black_player] {
+            if self
+                .seen_engines
+                .contains(engine, &game.event, strip_patterns)
+            {
+                continue;
+            }
+
+            if let Err(e) = self.seen_engines.add(engine, &game.event, strip_patterns) {
+                log.error(&format!("Unable to write seen engine to file: {:?}", e));
+            }
+
+            if self.first_poll {
+                continue;
+            }
+
+            // Anyone who wants to hear less about the engine doesn't want this either
+            let is_cooling_down = notify_config.cooldown(engine).is_some_and(|cooldown| {
+                self.engine_cooldowns
+                    .is_cooling_down(engine, strip_patterns, cooldown, now)
+            });
+            if is_cooling_down {
+                continue;
+            }
+
+            let mentions = get_engine_mentions(notify_config, engine, &game.event);
+            if mentions.is_empty() {
+                continue;
+            }
+
+            log.info(&format!(
+                "`{engine}` has entered {}, will notify {} users",
+                game.event,
+                mentions.len()
+            ));
+
+            self.entered_engines.push(new_content(
+                config,
+                notify_config,
+                NotifyKind::EngineEntered(engine.clone()),
+                game,
+                mentions,
+            ));
+        }
+    }
+
+    fn notify_started(
+        &mut self,
+        config: &Config,
+        notifier: &dyn Notifier,
+        notify_config: &NotifyConfig,
+        log: &dyn Logger,
+    ) {
+        let (contents, cooldowns): (Vec<_>, Vec<_>) = std::mem::take(&mut self.started_games)
+            .into_iter()
+            .map(|started_game| (started_game.content, started_game.cooldowns))
+            .unzip();
+
+        if config.batch_notifications && contents.len() > 1 {
+            match notify::notify_batch(config, notifier, &contents, log) {
+                Ok(true) => self.start_cooldowns(&cooldowns.concat(), notify_config, log),
+                Ok(false) => {}
+                Err(e) => log.error(&format!("Unable to send batched notify: {:?}", e)),
+            }
+            return;
+        }
+
+        for (content, cooldowns) in contents.into_iter().zip(cooldowns) {
+            match notify::notify(config, notifier, content, log) {
+                Ok(true) => self.start_cooldowns(&cooldowns, notify_config, log),
+                Ok(false) => {}
+                Err(e) => log.error(&format!("Unable to send notify: {:?}", e)),
+            }
+        }
+    }
+
+    fn notify_engines_entered(
+        &mut self,
+        config: &Config,
+        notifier: &dyn Notifier,
+        log: &dyn Logger,
+    ) {
+        for content in std::mem::take(&mut self.entered_engines) {
+            if let Err(e) = notify::notify(config, notifier, content, log) {
+                log.error(&format!("Unable to send engine entered notify: {:?}", e));
+            }
+        }
+    }
+}
+
+/// Fetch the current games and notify for anything new, returning the games that were checked.
+/// Fetching errors are returned, but failing to notify or write state is only logged.
+pub fn poll_once(
+    config: &Config,
+    notify_config: &NotifyConfig,
+    tracker: &mut GameTracker,
+    client: &dyn HttpClient,
+    cache: &mut PgnCache,
+    notifier: &dyn Notifier,
+    log: &dyn Logger,
+) -> Result<CurrentGames> {
+    let CurrentGames::Games(current_games) = tcec::get_current_games(config, client, cache)? else {
+        return Ok(CurrentGames::Unchanged);
+    };
+
+    let mut current_games = current_games
+        .into_iter()
+        .filter(|game| config.event_allowlist.allows(&game.event))
+        .filter(|game| !game.is_stale(config.stale_game_age, SystemTime::now()))
+        // Games which weren't played at the board aren't worth telling anyone about
+        .filter(|game| !game.is_forfeit())
+        .collect::<Vec<_>>();
+
+    if current_games.len() > config.max_games_per_poll {
+        log.warning(&format!(
+            "PGN has {} games, only looking at the first {}",
+            current_games.len(),
+            config.max_games_per_poll
+        ));
+        current_games.truncate(config.max_games_per_poll);
+    }
+
+    for game in &current_games {
+        tracker.handle_game(config, notifier, notify_config, game, log);
+    }
+
+    tracker.notify_started(config, notifier, notify_config, log);
+    tracker.notify_engines_entered(config, notifier, log);
+    tracker.first_poll = false;
+
+    Ok(CurrentGames::Games(current_games))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EventFilter;
+    use crate::http::{FakeHttpClient, HttpResponse};
+    use crate::log::RecordingLogger;
+    use crate::notify::{RecordingNotifier, SentNotification};
+    use crate::standings::Standings;
+    use regex::Regex;
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    const EVENT: &str = "TCEC Season 29 - Category 1 Playoff";
+    const OUT_OF_BOOK: &str = "1. e4 {book,} c5 {book,} 2. Nf3 {d=32, wv=0.50, tl=1800000,}";
+
+    fn game(white: &str, black: &str, round: &str, moves: &str, result: &str) -> String {
+        format!(
+            r#"[Event "{EVENT}"]
+[Date "2025.12.02"]
+[Round "{round}"]
+[White "{white}"]
+[Black "{black}"]
+[Result "{result}"]
+
+{moves} {result}
+"#
+        )
+    }
+
+    fn following(follows: &[(&str, &[&str])]) -> HashMap<String, HashMap<String, EventFilter>> {
+        follows
+            .iter()
+            .map(|(engine, users)| {
+                let users = users
+                    .iter()
+                    .map(|user| (user.to_string(), EventFilter::All))
+                    .collect();
+                (engine.to_string(), users)
+            })
+            .collect()
+    }
+
+    fn test_state_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!(
+                "tcec-notifier-test-{}-{}",
+                name,
+                std::process::id()
+            ))
+            .join(crate::state::DEFAULT_STATE_PATH)
+    }
+
+    // Polls the given PGN as though it had just been fetched, keeping track of what was sent
+    struct Poller {
+        config: Config,
+        notify_config: NotifyConfig,
+        tracker: GameTracker,
+        notifier: RecordingNotifier,
+        log: RecordingLogger,
+    }
+
+    impl Poller {
+        fn new(config: Config, notify_config: NotifyConfig) -> Self {
+            let standings = Arc::new(Mutex::new(Standings::in_memory()));
+            Self::with_tracker(config, notify_config, GameTracker::in_memory(standings))
+        }
+
+        fn with_tracker(config: Config, notify_config: NotifyConfig, tracker: GameTracker) -> Self {
+            Self {
+                config,
+                notify_config,
+                tracker,
+                notifier: RecordingNotifier::default(),
+                log: RecordingLogger::default(),
+            }
+        }
+
+        fn poll(&mut self, pgn: &str) -> Vec<SentNotification> {
+            let client = FakeHttpClient::new(vec![Ok(HttpResponse {
+                status: 200,
+                body: pgn.to_string(),
+                ..Default::default()
+            })]);
+
+            poll_once(
+                &self.config,
+                &self.notify_config,
+                &mut self.tracker,
+                &client,
+                &mut PgnCache::default(),
+                &self.notifier,
+                &self.log,
+            )
+            .unwrap();
+
+            self.notifier.take_sent()
+        }
+    }
+
+    fn descriptions(sent: &[SentNotification]) -> Vec<Option<&str>> {
+        sent.iter()
+            .map(|notification| notification.description.as_deref())
+            .collect()
+    }
+
+    fn followers_of_c4ke() -> NotifyConfig {
+        NotifyConfig {
+            engines: following(&[("c4ke", &["123"])]),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_start_and_result_are_each_notified_once() {
+        let mut poller = Poller::new(Config::for_tests(), followers_of_c4ke());
+        let in_progress = game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*");
+        let finished = game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "1-0");
+
+        let started = poller.poll(&in_progress);
+        assert_eq!(
+            descriptions(&started),
+            vec![Some("Out of book after 2 plies")]
+        );
+        assert_eq!(started[0].mentions, vec![Mention::parse("123")]);
+        assert!(poller.poll(&in_progress).is_empty());
+
+        assert_eq!(
+            descriptions(&poller.poll(&finished)),
+            vec![Some("`c4ke 1.1` won (1-0)")]
+        );
+        assert!(poller.poll(&finished).is_empty());
+    }
+
+    #[test]
+    fn test_game_notified_in_book_isnt_notified_again_once_out_of_it() {
+        let config = Config {
+            notify_in_book: true,
+            ..Config::for_tests()
+        };
+        // Following the matchup rather than an engine, so the engine entering isn't notified
+        let notify_config = NotifyConfig {
+            matchups: HashMap::from([(
+                ["Minic".to_string(), "c4ke".to_string()],
+                HashSet::from(["123".to_string()]),
+            )]),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(config, notify_config);
+
+        let in_book = game("c4ke 1.1", "Minic 3.44", "1.1", "1. e4 {book,}", "*");
+        assert_eq!(descriptions(&poller.poll(&in_book)), vec![None]);
+        assert!(poller.poll(&in_book).is_empty());
+
+        let out_of_book = game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*");
+        assert!(poller.poll(&out_of_book).is_empty());
+    }
+
+    #[test]
+    fn test_eval_swing_is_notified_once() {
+        let config = Config {
+            eval_swing_threshold: Some(2.0),
+            ..Config::for_tests()
+        };
+        let mut poller = Poller::new(config, followers_of_c4ke());
+
+        let moves = format!("{OUT_OF_BOOK} Nc6 {{d=32, wv=0.40,}}");
+        poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*"));
+
+        let moves = format!("{moves} 3. d4 {{d=32, wv=3.00,}}");
+        let swung = game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*");
+        assert_eq!(
+            descriptions(&poller.poll(&swung)),
+            vec![Some("Eval swung by 2.60")]
+        );
+        assert!(poller.poll(&swung).is_empty());
+
+        // A smaller swing later on doesn't replace the largest
+        let moves = format!("{moves} cxd4 {{d=32, wv=2.00,}}");
+        assert!(poller
+            .poll(&game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_time_trouble_is_notified_once() {
+        let config = Config {
+            time_trouble_threshold: Some(Duration::from_secs(60)),
+            ..Config::for_tests()
+        };
+        let mut poller = Poller::new(config, followers_of_c4ke());
+
+        let moves = format!("{OUT_OF_BOOK} Nc6 {{d=32, wv=0.40, tl=1800000,}}");
+        poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*"));
+
+        let moves = format!("{moves} 3. d4 {{d=32, wv=0.50, tl=30000,}}");
+        assert_eq!(
+            descriptions(&poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*"))),
+            vec![Some("c4ke 1.1 is in time trouble, with 30s left")]
+        );
+
+        let moves = format!("{moves} cxd4 {{d=32, wv=0.40, tl=1790000,}} 4. Nxd4 {{tl=20000,}}");
+        assert!(poller
+            .poll(&game("c4ke 1.1", "Minic 3.44", "1.1", &moves, "*"))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_users_following_a_game_several_ways_are_mentioned_once() {
+        let notify_config = NotifyConfig {
+            engines: following(&[("c4ke", &["123"]), (ALL_ENGINES, &["123", "456"])]),
+            matchups: HashMap::from([(
+                ["Minic".to_string(), "c4ke".to_string()],
+                HashSet::from(["123".to_string(), "456".to_string()]),
+            )]),
+            events: HashMap::from([("456".to_string(), EventFilter::All)]),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(Config::for_tests(), notify_config);
+
+        let sent = poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*"));
+
+        assert_eq!(sent.len(), 1);
+        assert_eq!(
+            sent[0].mentions,
+            vec![Mention::parse("123"), Mention::parse("456")]
+        );
+    }
+
+    #[test]
+    fn test_cooldown_quietens_only_the_engine_cooling_down() {
+        let notify_config = NotifyConfig {
+            engines: following(&[("c4ke", &["123"]), ("Minic", &["456"])]),
+            cooldowns: HashMap::from([("c4ke".to_string(), Duration::from_secs(60 * 60))]),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(Config::for_tests(), notify_config);
+
+        let first = poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*"));
+        let second = poller.poll(&game("Minic 3.44", "c4ke 1.1", "1.2", OUT_OF_BOOK, "*"));
+
+        assert_eq!(
+            first[0].mentions,
+            vec![Mention::parse("123"), Mention::parse("456")]
+        );
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].mentions, vec![Mention::parse("456")]);
+    }
+
+    #[test]
+    fn test_cooldown_only_starts_once_followers_are_notified() {
+        let notify_config = NotifyConfig {
+            engines: following(&[("c4ke", &["123"])]),
+            cooldowns: HashMap::from([("c4ke".to_string(), Duration::from_secs(60 * 60))]),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(Config::for_tests(), notify_config);
+
+        poller.notifier.fail = true;
+        poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*"));
+        poller.notifier.fail = false;
+        let second = poller.poll(&game("Minic 3.44", "c4ke 1.1", "1.2", OUT_OF_BOOK, "*"));
+        let third = poller.poll(&game("c4ke 1.1", "Stockfish", "1.3", OUT_OF_BOOK, "*"));
+
+        assert_eq!(second[0].mentions, vec![Mention::parse("123")]);
+        assert_eq!(third.len(), 1);
+        assert!(third[0].mentions.is_empty());
+    }
+
+    #[test]
+    fn test_cooldown_doesnt_start_without_followers_of_the_event() {
+        let follows_in = |event: &str| {
+            let events = EventFilter::Events(vec![Regex::new(event).unwrap()]);
+            HashMap::from([(
+                "c4ke".to_string(),
+                HashMap::from([("123".to_string(), events)]),
+            )])
+        };
+        let notify_config = NotifyConfig {
+            engines: follows_in("Cup"),
+            cooldowns: HashMap::from([("c4ke".to_string(), Duration::from_secs(60 * 60))]),
+            ..Default::default()
+        };
+        let mut poller = Poller::new(Config::for_tests(), notify_config);
+
+        poller.poll(&game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*"));
+        poller.notify_config.engines = follows_in("Category 1");
+        let second = poller.poll(&game("Minic 3.44", "c4ke 1.1", "1.2", OUT_OF_BOOK, "*"));
+
+        assert_eq!(second[0].mentions, vec![Mention::parse("123")]);
+    }
+
+    #[test]
+    fn test_games_with_ignored_engines_are_seen_without_notifying() {
+        let notify_config = NotifyConfig {
+            ignored_engines: vec!["TestEngine".to_string()],
+            ..followers_of_c4ke()
+        };
+        let mut poller = Poller::new(Config::for_tests(), notify_config);
+
+        for pgn in [
+            game("c4ke 1.1", "TestEngine dev-123", "1.1", OUT_OF_BOOK, "*"),
+            game("TestEngine dev-123", "c4ke 1.1", "1.2", OUT_OF_BOOK, "*"),
+        ] {
+            assert!(poller.poll(&pgn).is_empty());
+
+            // So it isn't a new game if the engine is unignored while it's being played
+            let game = tcec_pgn::get_pgn_info(&pgn).unwrap().unwrap();
+            assert!(poller.tracker.seen_games.contains(&game));
+        }
+    }
+
+    #[test]
+    fn test_engines_already_playing_when_starting_up_havent_entered() {
+        let state_path = test_state_path("engines-entered");
+        let notify_config = || NotifyConfig {
+            engines: following(&[("c4ke", &["123"]), ("Leela", &["456"])]),
+            ..Default::default()
+        };
+        let load = || {
+            let standings = Arc::new(Mutex::new(Standings::in_memory()));
+            let tracker = GameTracker::load(&state_path, standings, &RecordingLogger::default());
+            Poller::with_tracker(Config::for_tests(), notify_config(), tracker.unwrap())
+        };
+
+        let first_game = game("c4ke 1.1", "Minic 3.44", "1.1", OUT_OF_BOOK, "*");
+        let second_game = game("Leela 0.31", "Minic 3.44", "1.2", OUT_OF_BOOK, "*");
+
+        let mut poller = load();
+        let first_sent = poller.poll(&first_game);
+        poller.tracker.sync(&poller.log);
+
+        // Upgrading from before engines were tracked starts without the engines file
+        std::fs::remove_file(state_path.with_file_name("engines.bin")).unwrap();
+        let mut restarted = load();
+        let restarted_sent = restarted.poll(&first_game);
+        let second_sent = restarted.poll(&second_game);
+        std::fs::remove_dir_all(state_path.parent().unwrap()).unwrap();
+
+        assert_eq!(
+            descriptions(&first_sent),
+            vec![Some("Out of book after 2 plies")]
+        );
+        assert!(restarted_sent.is_empty());
+
+        // The engine entered's notification comes after its game's
+        assert_eq!(
+            descriptions(&second_sent),
+            vec![
+                Some("Out of book after 2 plies"),
+                Some(&*format!("Leela 0.31's first game in {EVENT}")),
+            ]
+        );
+        assert_eq!(second_sent[1].mentions, vec![Mention::parse("456")]);
+    }
+}
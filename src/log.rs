@@ -1,11 +1,24 @@
-use crate::config::Config;
+use crate::config::{Config, DiscordIdentity, LogFormat};
+use crate::http::ReqwestClient;
+use crate::notify::Mention;
 use crate::{discord, log};
+use serde_json::json;
 use std::panic::PanicHookInfo;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_logger(config: &Config) -> Box<dyn Logger> {
+    if config.log_format == LogFormat::Json {
+        return Box::new(log::JsonLogger);
+    }
+
     match config.log_webhook {
         None => Box::new(log::StdoutLogger),
-        Some(ref hook) => Box::new(log::DiscordLogger::new(hook.clone())),
+        Some(ref hook) => Box::new(log::DiscordLogger::new(
+            config.http_client.clone(),
+            hook.clone(),
+            config.discord_identity.clone(),
+            config.admin_mention.clone(),
+        )),
     }
 }
 
@@ -71,41 +84,113 @@ impl Logger for StdoutLogger {
     }
 }
 
+/// Writes one JSON object per line, for shipping logs to a collector
+pub struct JsonLogger;
+
+impl JsonLogger {
+    fn log(&self, level: &str, msg: &str) {
+        println!("{}", Self::format_line(level, msg, SystemTime::now()));
+    }
+
+    // The timestamp is in seconds since the Unix epoch
+    fn format_line(level: &str, msg: &str, time: SystemTime) -> String {
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map(|since_epoch| since_epoch.as_secs_f64())
+            .unwrap_or_default();
+
+        json!({
+            "level": level,
+            "message": msg,
+            "timestamp": timestamp,
+        })
+        .to_string()
+    }
+}
+
+impl Logger for JsonLogger {
+    fn start(&self) {}
+
+    fn info(&self, msg: &str) {
+        self.log("info", msg);
+    }
+
+    fn warning(&self, msg: &str) {
+        self.log("warning", msg);
+    }
+
+    fn error(&self, msg: &str) {
+        self.log("error", msg);
+    }
+
+    fn panic(&self, info: &PanicHookInfo) {
+        self.log("panic", &get_panic_message(info));
+    }
+}
+
 #[derive(Clone)]
 pub struct DiscordLogger {
+    client: ReqwestClient,
     log_webhook: String,
+    identity: DiscordIdentity,
+    admin_mention: Option<Mention>,
 }
 
 impl DiscordLogger {
-    pub fn new(log_webhook: String) -> DiscordLogger {
-        Self { log_webhook }
+    pub fn new(
+        client: ReqwestClient,
+        log_webhook: String,
+        identity: DiscordIdentity,
+        admin_mention: Option<Mention>,
+    ) -> DiscordLogger {
+        Self {
+            client,
+            log_webhook,
+            identity,
+            admin_mention,
+        }
+    }
+
+    // Only the admin can be pinged, wherever they're mentioned in the message
+    fn send(&self, msg: &str) {
+        let _ = discord::send_message(
+            &self.client,
+            &self.log_webhook,
+            &self.identity,
+            msg,
+            &self.admin_mention.iter().cloned().collect(),
+        );
+    }
+
+    fn send_to_admin(&self, msg: &str) {
+        match &self.admin_mention {
+            Some(admin) => self.send(&format!("{admin} {msg}")),
+            None => self.send(msg),
+        }
     }
 }
 
 impl Logger for DiscordLogger {
     fn start(&self) {
-        let _ = discord::send_message(&self.log_webhook, "```───────────────────────────────────────────────────────────────────────────────────────────────────────────```");
+        self.send("```───────────────────────────────────────────────────────────────────────────────────────────────────────────```");
     }
 
     fn info(&self, msg: &str) {
         println!("{}", msg);
 
-        let _ = discord::send_message(&self.log_webhook, msg);
+        self.send(msg);
     }
 
     fn warning(&self, msg: &str) {
         println!(":yellow_circle: {}", msg);
 
-        let _ = discord::send_message(&self.log_webhook, msg);
+        self.send(msg);
     }
 
     fn error(&self, msg: &str) {
         eprintln!("{}", msg);
 
-        let _ = discord::send_message(
-            &self.log_webhook,
-            &("<@!106120945231466496> :red_circle:".to_string() + msg),
-        );
+        self.send_to_admin(&format!(":red_circle:{msg}"));
     }
 
     fn panic(&self, info: &PanicHookInfo) {
@@ -113,9 +198,82 @@ impl Logger for DiscordLogger {
 
         eprintln!("{}", msg);
 
-        let _ = discord::send_message(
-            &self.log_webhook,
-            &("<@!106120945231466496> :fire: :fire: :fire: ".to_string() + &msg),
+        self.send_to_admin(&format!(":fire: :fire: :fire: {msg}"));
+    }
+}
+
+/// Keeps everything it's asked to log, along with its level, so tests can check what was logged
+#[cfg(test)]
+#[derive(Default)]
+pub struct RecordingLogger {
+    messages: std::cell::RefCell<Vec<(&'static str, String)>>,
+}
+
+#[cfg(test)]
+impl RecordingLogger {
+    /// The messages logged at a level, e.g. `"warning"`, in the order they were logged
+    pub fn messages(&self, level: &str) -> Vec<String> {
+        self.messages
+            .borrow()
+            .iter()
+            .filter(|(message_level, _)| *message_level == level)
+            .map(|(_, message)| message.clone())
+            .collect()
+    }
+
+    fn log(&self, level: &'static str, msg: &str) {
+        self.messages.borrow_mut().push((level, msg.to_string()));
+    }
+}
+
+#[cfg(test)]
+impl Logger for RecordingLogger {
+    fn start(&self) {}
+
+    fn info(&self, msg: &str) {
+        self.log("info", msg);
+    }
+
+    fn warning(&self, msg: &str) {
+        self.log("warning", msg);
+    }
+
+    fn error(&self, msg: &str) {
+        self.log("error", msg);
+    }
+
+    fn panic(&self, info: &PanicHookInfo) {
+        self.log("panic", &get_panic_message(info));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_recording_logger_keeps_levels_apart() {
+        let log = RecordingLogger::default();
+        log.info("Loaded config");
+        log.error("Unable to send notify");
+        log.info("Shutting down cleanly");
+
+        assert_eq!(
+            log.messages("info"),
+            vec!["Loaded config", "Shutting down cleanly"]
+        );
+        assert_eq!(log.messages("error"), vec!["Unable to send notify"]);
+        assert!(log.messages("warning").is_empty());
+    }
+
+    #[test]
+    fn test_json_logger_line() {
+        let time = UNIX_EPOCH + Duration::from_millis(1_700_000_000_500);
+
+        assert_eq!(
+            JsonLogger::format_line("warning", "Unable to fetch \"config\"", time),
+            r#"{"level":"warning","message":"Unable to fetch \"config\"","timestamp":1700000000.5}"#
         );
     }
 }
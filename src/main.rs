@@ -1,29 +1,124 @@
-use crate::config::NotifyConfig;
-use crate::log::Logger;
-use crate::notify::NotifyContent;
-use crate::state::SeenGames;
-use anyhow::Result;
-use std::cmp::PartialEq;
+use anyhow::{bail, Context, Result};
 use std::collections::HashSet;
-use std::time::Duration;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tcec_notifier::backoff::{self, Backoff};
+use tcec_notifier::config::{self, Config, ConfigDebounce, NotifyConfigError};
+use tcec_notifier::health::{self, SharedHealth};
+use tcec_notifier::http::HttpClient;
+use tcec_notifier::log::{self, Logger};
+use tcec_notifier::notify::Notifiers;
+use tcec_notifier::standings::{SharedStandings, Standings};
+use tcec_notifier::tcec::PgnCache;
+use tcec_notifier::tcec_pgn;
+use tcec_notifier::{discord, metrics, CurrentGames, GameTracker};
 
-mod config;
-mod discord;
-mod log;
-mod notify;
-mod state;
-mod tcec;
-mod tcec_pgn;
+const MAX_BACKOFF_DELAY: Duration = Duration::from_secs(10 * 60);
+const PV_PREVIEW_MOVES: usize = 5;
+const SHUTDOWN_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const TEST_MESSAGE: &str = "Test message from tcec-notifier";
 
-const POLL_DELAY: Duration = Duration::from_secs(30);
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
 
-impl PartialEq for NotifyConfig {
-    fn eq(&self, other: &Self) -> bool {
-        self.engines == other.engines
+fn shutdown_requested() -> bool {
+    SHUTDOWN_REQUESTED.load(Ordering::SeqCst)
+}
+
+/// Sleep for the given duration, waking early if we've been asked to shut down.
+fn sleep(duration: Duration) {
+    let deadline = Instant::now() + duration;
+
+    while !shutdown_requested() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            break;
+        }
+
+        std::thread::sleep(remaining.min(SHUTDOWN_CHECK_INTERVAL));
+    }
+}
+
+/// Make sure the Discord webhooks exist, so a mistake in one is found now rather than when the
+/// first game starts
+fn check_webhooks(config: &Config, client: &dyn HttpClient, log: &dyn Logger) -> Result<()> {
+    let notify_webhooks = config
+        .notify_webhooks
+        .iter()
+        .enumerate()
+        .map(|(i, webhook)| (format!("notify webhook {}", i + 1), webhook));
+    let log_webhook = config
+        .log_webhook
+        .iter()
+        .map(|webhook| ("log webhook".to_string(), webhook));
+
+    for (name, webhook) in notify_webhooks.chain(log_webhook) {
+        let Err(e) = discord::check_webhook(client, webhook) else {
+            continue;
+        };
+
+        if config.require_valid_webhooks {
+            return Err(e.context(format!("Unable to reach {name}")));
+        }
+
+        log.warning(&format!(
+            "Unable to reach {name}, notifications may not be sent: {:?}",
+            e
+        ));
+    }
+
+    Ok(())
+}
+
+/// Print the game in a PGN file as JSON, to check how it's been parsed
+fn dump_pgn(path: &Path) -> Result<()> {
+    let pgn = std::fs::read_to_string(path)
+        .with_context(|| format!("Unable to read {}", path.display()))?;
+    let Some(game) = tcec_pgn::get_pgn_info(&pgn)? else {
+        bail!("{} doesn't contain a game", path.display());
+    };
+
+    println!("{}", serde_json::to_string_pretty(&game.to_json())?);
+
+    Ok(())
+}
+
+/// Send a message to each notify webhook, to check they're set up without waiting for a game
+fn test_notify() -> Result<()> {
+    let config = config::get_config()?;
+
+    if config.notify_webhooks.is_empty() {
+        bail!("No notify webhook is configured, set TCEC_NOTIFY_WEBHOOK");
     }
+
+    for (i, webhook) in config.notify_webhooks.iter().enumerate() {
+        let name = format!("notify webhook {}", i + 1);
+
+        discord::send_message(
+            &config.http_client,
+            webhook,
+            &config.discord_identity,
+            TEST_MESSAGE,
+            &HashSet::new(),
+        )
+        .with_context(|| format!("Unable to send to {name}"))?;
+
+        println!("Sent a test message to {name}");
+    }
+
+    Ok(())
 }
 
 fn main() -> Result<()> {
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => {}
+        [command] if command == "test-notify" => return test_notify(),
+        [command, path] if command == "dump-pgn" => return dump_pgn(Path::new(path)),
+        _ => bail!("Usage: tcec-notifier [test-notify | dump-pgn <file>]"),
+    }
+
     let config = config::get_config().expect("Unable to load config");
     let log = log::get_logger(&config);
 
@@ -34,103 +129,225 @@ fn main() -> Result<()> {
         log.panic(info);
     }));
 
+    ctrlc::set_handler(|| SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst))
+        .expect("Unable to install signal handler");
+
     log.start();
 
+    for warning in &config.warnings {
+        log.warning(warning);
+    }
+
+    if config.dry_run {
+        log.info("Dry run: notifications will be logged instead of sent");
+    }
+
+    let http_client = config.http_client.clone();
+
+    check_webhooks(&config, &http_client, &log)?;
+
+    if let Some(pgn_file) = &config.pgn_file {
+        log.info(&format!("Reading games from {}", pgn_file.display()));
+    }
+
+    let health = SharedHealth::default();
+    let standings = if config.no_state {
+        log.info("Not keeping state, games seen will be forgotten on exit");
+        Standings::in_memory()
+    } else {
+        Standings::load(&config.state_path, &log).expect("Unable to load standings")
+    };
+    let standings: SharedStandings = Arc::new(Mutex::new(standings));
+
+    if let Some(port) = config.health_port {
+        match health::start(port, health.clone(), standings.clone()) {
+            Ok(()) => log.info(&format!("Serving health checks on port {port}")),
+            Err(e) => log.error(&format!("Unable to start health check server: {:?}", e)),
+        }
+    }
+
+    if let Some(port) = config.metrics_port {
+        match metrics::start(port) {
+            Ok(()) => log.info(&format!("Serving metrics on port {port}")),
+            Err(e) => log.error(&format!("Unable to start metrics server: {:?}", e)),
+        }
+    }
+
     let mut first_run = true;
+    let mut pgn_backoff = Backoff::new(config.poll_delay, MAX_BACKOFF_DELAY);
+    let mut pgn_cache = PgnCache::default();
 
-    let mut seen_games = SeenGames::load().expect("Unable to load state");
-    let mut notify_config = config::get_notify_config(&config).expect("Unable to load config");
+    let mut tracker = if config.no_state {
+        GameTracker::in_memory(standings)
+    } else {
+        GameTracker::load(&config.state_path, standings, &log).expect("Unable to load state")
+    };
+    let notifiers = Notifiers::from_config(&config);
 
-    log.info(&format!("Loaded config: {:?}", notify_config));
+    let mut notify_config =
+        config::get_notify_config(&config, &http_client).expect("Unable to load config");
+
+    log.info(&notify_config.summary());
+    for warning in &notify_config.warnings {
+        log.warning(warning);
+    }
+    let mut last_config_reload = Instant::now();
+    let mut config_debounce = ConfigDebounce::new(config.config_stable_fetches);
+
+    let mut run_result = Ok(());
 
     loop {
-        let new_notify_config = config::get_notify_config(&config);
-        if let Err(e) = new_notify_config {
-            log.warning(&format!("Unable to fetch new config: {:?}", e));
-        } else {
-            let new_notify_config = new_notify_config?;
-            if notify_config != new_notify_config {
-                log.info(&format!(
-                    "<@!106120945231466496> Config update loaded: {:?}",
-                    new_notify_config
-                ));
-                notify_config = new_notify_config;
+        if shutdown_requested() {
+            break;
+        }
+
+        if last_config_reload.elapsed() >= config.config_reload_delay {
+            last_config_reload = Instant::now();
+
+            match config::get_notify_config(&config, &http_client) {
+                // Probably a network blip, which will sort itself out
+                Err(NotifyConfigError::Unavailable(e)) => {
+                    log.warning(&format!("Unable to fetch new config: {:?}", e));
+                }
+                // Someone's edit has broken the config, or it's no longer at the URL, which won't
+                // fix itself
+                Err(NotifyConfigError::Invalid(e)) => {
+                    log.error(&format!(
+                        "New config is invalid, keeping the old one: {:?}",
+                        e
+                    ));
+                }
+                Ok(fetched_notify_config) => {
+                    if let Some(new_notify_config) =
+                        config_debounce.update(&notify_config, fetched_notify_config)
+                    {
+                        let admin_mention = config
+                            .admin_mention
+                            .as_ref()
+                            .map(|admin| format!("{admin} "))
+                            .unwrap_or_default();
+
+                        log.info(&format!(
+                            "{admin_mention}Config update loaded: {}",
+                            new_notify_config.summary()
+                        ));
+                        for warning in &new_notify_config.warnings {
+                            log.warning(warning);
+                        }
+
+                        notify_config = new_notify_config;
+                        metrics::increment(&metrics::CONFIG_RELOADS);
+                    }
+                }
             }
         }
 
-        let current_game_result = tcec::get_current_game(&log);
+        let current_games_result = tcec_notifier::poll_once(
+            &config,
+            &notify_config,
+            &mut tracker,
+            &http_client,
+            &mut pgn_cache,
+            &notifiers,
+            &log,
+        );
+        metrics::increment(&metrics::POLLS);
 
-        let Ok(current_game) = current_game_result else {
-            let e = current_game_result.unwrap_err();
+        let Ok(current_games) = current_games_result else {
+            let e = current_games_result.unwrap_err();
+            health.lock().unwrap().record_error(format!("{:?}", e));
+            metrics::increment(&metrics::POLL_FAILURES);
+            metrics::increment(&metrics::CONSECUTIVE_POLL_FAILURES);
 
-            log.warning(&format!("Unable to fetch in-progress game: {:?}", e));
+            if config.run_once {
+                run_result = Err(e.context("Unable to fetch in-progress game"));
+                break;
+            }
 
-            std::thread::sleep(POLL_DELAY);
-            continue;
-        };
+            // Only warn when the delay changes, so an extended outage doesn't flood the log
+            if pgn_backoff.record_failure() {
+                log.warning(&format!(
+                    "Unable to fetch in-progress game, retrying in {}s: {:?}",
+                    pgn_backoff.delay().as_secs(),
+                    e
+                ));
+            }
 
-        let Some(game) = current_game else {
-            // We might have a game that's in its opening and hasn't 'started' yet
-            std::thread::sleep(POLL_DELAY);
+            sleep(backoff::jitter(pgn_backoff.delay(), config::MIN_POLL_DELAY));
             continue;
         };
 
-        if first_run {
+        health.lock().unwrap().record_success();
+        metrics::set(&metrics::CONSECUTIVE_POLL_FAILURES, 0);
+
+        let failures = pgn_backoff.record_success();
+        if failures > 0 {
             log.info(&format!(
-                "In progress: `{}` vs `{}` ({} plies)",
-                game.white_player,
-                game.black_player,
-                game.moves.len()
+                "Fetched in-progress game after {failures} failed attempts"
             ));
-
-            first_run = false;
         }
 
-        if seen_games.contains(&game) {
-            // Already seen this game - just wait
-            std::thread::sleep(POLL_DELAY);
-            continue;
-        }
+        let CurrentGames::Games(current_games) = current_games else {
+            if config.run_once {
+                break;
+            }
 
-        // If we got this far, we've got a new game
-        log.info(&format!(
-            "`{}` vs `{}`",
-            game.white_player, game.black_player,
-        ));
+            // Nothing has changed since the last poll
+            sleep(backoff::jitter(config.poll_delay, config::MIN_POLL_DELAY));
+            continue;
+        };
 
-        let mut mentions = HashSet::new();
+        // This only logs what was already going on when we started. Those games have already been
+        // notified about by `poll_once`, unless the state file says we've notified for them before.
+        if first_run {
+            for game in &current_games {
+                let eval_str = game
+                    .latest_eval()
+                    .map(|eval| format!(", eval {eval}"))
+                    .unwrap_or_default();
+                let pv_str = game
+                    .latest_pv()
+                    .map(|pv| {
+                        let preview = pv[..pv.len().min(PV_PREVIEW_MOVES)].join(" ");
+                        let ellipsis = if pv.len() > PV_PREVIEW_MOVES {
+                            " ..."
+                        } else {
+                            ""
+                        };
+                        format!(", pv {preview}{ellipsis}")
+                    })
+                    .unwrap_or_default();
+                let material_str = game
+                    .material_balance()
+                    .and_then(|balance| balance.describe())
+                    .map(|material| format!(", {material}"))
+                    .unwrap_or_default();
 
-        for (engine, notifies) in &notify_config.engines {
-            if game.has_player(engine) {
-                mentions.extend(notifies.iter().cloned());
                 log.info(&format!(
-                    "Will notify {} users for engine `{}`",
-                    notifies.len(),
-                    &engine,
+                    "In progress: `{}` vs `{}` ({} moves{}{}{})",
+                    game.white_player,
+                    game.black_player,
+                    game.full_move_count(),
+                    eval_str,
+                    material_str,
+                    pv_str
                 ));
             }
-        }
-
-        let notify_result = notify::notify(
-            &config,
-            NotifyContent {
-                tournament: game.event.clone(),
-                white_player: game.white_player.clone(),
-                black_player: game.black_player.clone(),
-                mentions,
-            },
-        );
 
-        if let Err(e) = notify_result {
-            log.error(&format!("Unable to send notify: {:?}", e));
+            // We might have a game that's in its opening and hasn't 'started' yet
+            first_run = current_games.is_empty();
         }
 
-        let write_state_result = seen_games.add(&game);
-
-        if let Err(e) = write_state_result {
-            log.error(&format!("Unable to write seen game to file: {:?}", e));
+        if config.run_once {
+            break;
         }
 
-        std::thread::sleep(POLL_DELAY);
+        sleep(backoff::jitter(config.poll_delay, config::MIN_POLL_DELAY));
     }
+
+    tracker.sync(&log);
+
+    log.info("Shutting down cleanly");
+
+    run_result
 }
@@ -0,0 +1,120 @@
+//! Counters describing what the notifier has been doing, optionally served in the Prometheus
+//! text format.
+
+use anyhow::{anyhow, Result};
+use std::sync::atomic::{AtomicU64, Ordering};
+use tiny_http::{Header, Response, Server};
+
+pub static POLLS: AtomicU64 = AtomicU64::new(0);
+pub static POLL_FAILURES: AtomicU64 = AtomicU64::new(0);
+pub static CONSECUTIVE_POLL_FAILURES: AtomicU64 = AtomicU64::new(0);
+pub static NOTIFICATIONS_SENT: AtomicU64 = AtomicU64::new(0);
+pub static CONFIG_RELOADS: AtomicU64 = AtomicU64::new(0);
+
+enum MetricKind {
+    Counter,
+    Gauge,
+}
+
+struct Metric {
+    name: &'static str,
+    help: &'static str,
+    kind: MetricKind,
+    value: &'static AtomicU64,
+}
+
+const METRICS: [Metric; 5] = [
+    Metric {
+        name: "tcec_notifier_polls_total",
+        help: "Attempts to fetch the current game",
+        kind: MetricKind::Counter,
+        value: &POLLS,
+    },
+    Metric {
+        name: "tcec_notifier_poll_failures_total",
+        help: "Attempts to fetch the current game which failed",
+        kind: MetricKind::Counter,
+        value: &POLL_FAILURES,
+    },
+    Metric {
+        name: "tcec_notifier_consecutive_poll_failures",
+        help: "Attempts to fetch the current game which have failed since the last success",
+        kind: MetricKind::Gauge,
+        value: &CONSECUTIVE_POLL_FAILURES,
+    },
+    Metric {
+        name: "tcec_notifier_notifications_sent_total",
+        help: "Notifications successfully sent, counting each destination separately",
+        kind: MetricKind::Counter,
+        value: &NOTIFICATIONS_SENT,
+    },
+    Metric {
+        name: "tcec_notifier_config_reloads_total",
+        help: "Changes to the notify config which have been loaded",
+        kind: MetricKind::Counter,
+        value: &CONFIG_RELOADS,
+    },
+];
+
+pub fn increment(metric: &AtomicU64) {
+    metric.fetch_add(1, Ordering::Relaxed);
+}
+
+pub fn set(metric: &AtomicU64, value: u64) {
+    metric.store(value, Ordering::Relaxed);
+}
+
+fn render() -> String {
+    METRICS
+        .iter()
+        .map(|metric| {
+            let kind = match metric.kind {
+                MetricKind::Counter => "counter",
+                MetricKind::Gauge => "gauge",
+            };
+
+            format!(
+                "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {value}\n",
+                name = metric.name,
+                help = metric.help,
+                value = metric.value.load(Ordering::Relaxed),
+            )
+        })
+        .collect()
+}
+
+/// Serve the metrics on a background thread
+pub fn start(port: u16) -> Result<()> {
+    let server = Server::http(("0.0.0.0", port))
+        .map_err(|e| anyhow!("Unable to listen on port {port}: {e}"))?;
+
+    std::thread::spawn(move || {
+        for request in server.incoming_requests() {
+            let response = Response::from_string(render()).with_header(
+                Header::from_bytes(&b"Content-Type"[..], &b"text/plain; version=0.0.4"[..])
+                    .unwrap(),
+            );
+
+            // The client may have gone away, which shouldn't stop us serving anyone else
+            let _ = request.respond(response);
+        }
+    });
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_format() {
+        increment(&CONFIG_RELOADS);
+
+        let rendered = render();
+
+        assert!(rendered.contains("# TYPE tcec_notifier_config_reloads_total counter\n"));
+        assert!(rendered.contains("# TYPE tcec_notifier_consecutive_poll_failures gauge\n"));
+        assert!(!rendered.contains("tcec_notifier_config_reloads_total 0\n"));
+    }
+}
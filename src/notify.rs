@@ -1,35 +1,734 @@
+use crate::audit;
 use crate::config::Config;
-use crate::discord;
-use crate::tcec::{EngineName, TCEC_URL};
-use anyhow::Result;
+use crate::discord::DiscordNotifier;
+use crate::log::Logger;
+use crate::metrics;
+use crate::slack::SlackNotifier;
+use crate::tcec::EngineName;
+use crate::tcec_pgn::{Eval, GameResult, Pgn, Side, TimeControl};
+use crate::telegram::TelegramNotifier;
+use anyhow::{bail, Result};
+use regex::{Captures, Regex};
+use reqwest::Url;
 use std::collections::HashSet;
+use std::fmt;
+use std::time::Duration;
+
+const TEMPLATE_PLACEHOLDERS: [&str; 6] =
+    ["white", "black", "tournament", "round", "url", "mentions"];
+
+const ROLE_PREFIX: &str = "role:";
+
+/// Someone to ping about a game. Roles are written in the config as `role:<id>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Mention {
+    User(String),
+    Role(String),
+}
+
+impl Mention {
+    pub fn parse(id: &str) -> Self {
+        match id.strip_prefix(ROLE_PREFIX) {
+            Some(role) => Mention::Role(role.trim().to_string()),
+            None => Mention::User(id.to_string()),
+        }
+    }
+
+    pub fn is_role(&self) -> bool {
+        matches!(self, Mention::Role(_))
+    }
+
+    /// The ID of the user or role, without any markup
+    pub fn id(&self) -> &str {
+        match self {
+            Mention::User(id) | Mention::Role(id) => id,
+        }
+    }
+}
+
+impl fmt::Display for Mention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mention::User(id) => write!(f, "<@!{id}>"),
+            Mention::Role(id) => write!(f, "<@&{id}>"),
+        }
+    }
+}
+
+pub enum NotifyKind {
+    Started,
+    Finished(GameResult),
+    EvalSwing(f32),
+
+    /// The engine is running low on time
+    TimeTrouble(EngineName, Duration),
+
+    /// The game is playing one of the openings being watched for
+    Opening(String),
+
+    /// The engine is playing its first game in the event
+    EngineEntered(EngineName),
+}
 
 pub struct NotifyContent {
+    pub kind: NotifyKind,
     pub white_player: EngineName,
     pub black_player: EngineName,
+    pub white_elo: Option<u32>,
+    pub black_elo: Option<u32>,
     pub tournament: String,
-    pub mentions: HashSet<String>,
-}
-
-pub fn notify(config: &Config, content: NotifyContent) -> Result<()> {
-    let mentions_str = if !content.mentions.is_empty() {
-        "   cc. ".to_string()
-            + content
-                .mentions
-                .iter()
-                .map(|m| format!("<@!{}>", m))
-                .collect::<Vec<_>>()
-                .join(" ")
-                .as_str()
-    } else {
-        String::new()
+
+    /// Which game of the match this is, e.g. `2.1`
+    pub round: Option<String>,
+    pub url: String,
+    pub eco: Option<String>,
+    pub opening: Option<String>,
+    pub variation: Option<String>,
+    pub time_control: Option<TimeControl>,
+
+    /// How many plies were played from book, once the game has left it
+    pub book_length: Option<usize>,
+
+    /// The latest evaluation, from white's perspective
+    pub eval: Option<Eval>,
+
+    /// The side the people being notified want to win, if they're all on the same one
+    pub followed_side: Option<Side>,
+    pub mentions: HashSet<Mention>,
+
+    /// The message for one of the engines playing, which is used instead of the usual template
+    pub engine_template: Option<MessageTemplate>,
+}
+
+impl NotifyContent {
+    pub fn new(kind: NotifyKind, game: &Pgn, site_url: &Url, mentions: HashSet<Mention>) -> Self {
+        Self {
+            kind,
+            white_player: game.white_player.clone(),
+            black_player: game.black_player.clone(),
+            white_elo: game.white_elo,
+            black_elo: game.black_elo,
+            tournament: game.event.clone(),
+            round: game.round.clone(),
+            url: game_url(game, site_url),
+            eco: game.eco.clone(),
+            opening: game.opening_name.clone(),
+            variation: game.variation.clone(),
+            time_control: game.time_control,
+            book_length: game.out_of_book().then(|| game.book_length()),
+            eval: game.latest_eval(),
+            followed_side: None,
+            mentions,
+            engine_template: None,
+        }
+    }
+
+    pub fn with_engine_template(mut self, engine_template: Option<MessageTemplate>) -> Self {
+        self.engine_template = engine_template;
+        self
+    }
+
+    pub fn with_followed_side(mut self, followed_side: Option<Side>) -> Self {
+        self.followed_side = followed_side;
+        self
+    }
+
+    /// The template to render this with, given the notifier's own
+    pub fn template<'a>(
+        &'a self,
+        template: &'a Option<MessageTemplate>,
+    ) -> Option<&'a MessageTemplate> {
+        self.engine_template.as_ref().or(template.as_ref())
+    }
+
+    pub fn title(&self) -> String {
+        format!(
+            "{}{} vs. {}{}{}",
+            self.white_player,
+            describe_elo(self.white_elo),
+            self.black_player,
+            describe_elo(self.black_elo),
+            self.describe_opening()
+        )
+    }
+
+    /// The tournament, along with the round and time control if we know them
+    pub fn footer(&self) -> String {
+        let round = self.describe_round();
+
+        match &self.time_control {
+            Some(time_control) => format!("{}{round} ({})", self.tournament, time_control),
+            None => format!("{}{round}", self.tournament),
+        }
+    }
+
+    pub fn description(&self) -> Option<String> {
+        match self.kind {
+            NotifyKind::Started => self
+                .book_length
+                .map(|book_length| format!("Out of book after {book_length} plies")),
+            NotifyKind::Finished(result) => self.describe_result(result),
+            NotifyKind::EvalSwing(swing) => Some(format!("Eval swung by {swing:.2}")),
+            NotifyKind::TimeTrouble(ref engine, remaining) => Some(format!(
+                "{engine} is in time trouble, with {}s left",
+                remaining.as_secs()
+            )),
+            NotifyKind::Opening(ref openings) => {
+                Some(format!("Playing watched opening {openings}"))
+            }
+            NotifyKind::EngineEntered(ref engine) => {
+                Some(format!("{engine}'s first game in {}", self.tournament))
+            }
+        }
+    }
+
+    /// The notification as plain text, for backends without rich embeds.
+    /// Each backend has its own markup for links, so it provides how to render them.
+    pub fn text(&self, link: impl Fn(&str, &str) -> String) -> String {
+        let mut lines = vec![link(&self.title(), &self.url)];
+        lines.extend(self.description());
+        lines.push(self.footer());
+
+        lines.join("\n")
+    }
+
+    fn describe_round(&self) -> String {
+        self.round
+            .as_ref()
+            .map(|round| format!(", Round {round}"))
+            .unwrap_or_default()
+    }
+
+    // The ECO code is given ahead of the opening's name, e.g. `B43 Sicilian`
+    fn describe_opening(&self) -> String {
+        let opening = [&self.eco, &self.opening]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        match (&self.opening, &self.variation) {
+            (Some(_), Some(variation)) => format!(" ({}, {})", opening, variation),
+            _ if opening.is_empty() => String::new(),
+            _ => format!(" ({})", opening),
+        }
+    }
+
+    fn describe_result(&self, result: GameResult) -> Option<String> {
+        match result {
+            GameResult::WhiteWin => Some(format!("`{}` won ({})", self.white_player, result)),
+            GameResult::BlackWin => Some(format!("`{}` won ({})", self.black_player, result)),
+            GameResult::Draw => Some(format!("Drawn ({})", result)),
+            GameResult::Ongoing => None,
+        }
+    }
+}
+
+/// A user-provided notification message, with placeholders like `{white}` filled in for each game
+#[derive(Debug, Clone, PartialEq)]
+pub struct MessageTemplate(String);
+
+impl MessageTemplate {
+    pub fn new(template: &str) -> Result<Self> {
+        let unknown = placeholder_regex()
+            .captures_iter(template)
+            .map(|caps| caps[1].to_string())
+            .filter(|placeholder| !TEMPLATE_PLACEHOLDERS.contains(&placeholder.as_str()))
+            .collect::<Vec<_>>();
+
+        if !unknown.is_empty() {
+            bail!(
+                "Unknown placeholders in message template: {}",
+                unknown.join(", ")
+            );
+        }
+
+        Ok(Self(template.to_string()))
+    }
+
+    /// Mentions are rendered differently by each backend, so they're passed in ready to use
+    pub fn render(&self, content: &NotifyContent, mentions: &str) -> String {
+        placeholder_regex()
+            .replace_all(&self.0, |caps: &Captures| match &caps[1] {
+                "white" => content.white_player.to_string(),
+                "black" => content.black_player.to_string(),
+                "tournament" => content.tournament.clone(),
+                "round" => content
+                    .round
+                    .as_ref()
+                    .map(|round| format!("Round {round}"))
+                    .unwrap_or_default(),
+                "url" => content.url.clone(),
+                "mentions" => mentions.to_string(),
+                _ => caps[0].to_string(),
+            })
+            .to_string()
+    }
+}
+
+fn placeholder_regex() -> Regex {
+    Regex::new(r"\{(\w+)\}").unwrap()
+}
+
+// Link to the site the game is being played on, as long as it looks like a web page
+fn game_url(game: &Pgn, site_url: &Url) -> String {
+    game.site
+        .as_ref()
+        .filter(|site| site.starts_with("https://") || site.starts_with("http://"))
+        .cloned()
+        .unwrap_or_else(|| site_url.to_string())
+}
+
+fn describe_elo(elo: Option<u32>) -> String {
+    elo.map(|elo| format!(" ({elo})")).unwrap_or_default()
+}
+
+/// A destination for game notifications
+pub trait Notifier {
+    fn name(&self) -> &'static str;
+    fn send(&self, content: &NotifyContent) -> Result<()>;
+
+    /// Send several notifications together in a single message
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()>;
+}
+
+/// Every notifier in the config, sent to together
+pub struct Notifiers(Vec<Box<dyn Notifier>>);
+
+impl Notifiers {
+    pub fn from_config(config: &Config) -> Self {
+        let mut notifiers: Vec<Box<dyn Notifier>> = vec![];
+
+        let template = &config.message_template;
+
+        for webhook in &config.notify_webhooks {
+            notifiers.push(Box::new(DiscordNotifier::new(
+                config.http_client.clone(),
+                webhook,
+                config.discord_identity.clone(),
+                template.clone(),
+            )));
+        }
+
+        if let Some(slack_webhook) = &config.slack_webhook {
+            notifiers.push(Box::new(SlackNotifier::new(
+                config.http_client.clone(),
+                slack_webhook,
+                template.clone(),
+            )));
+        }
+
+        if let Some(telegram) = &config.telegram {
+            notifiers.push(Box::new(TelegramNotifier::new(
+                config.http_client.clone(),
+                telegram,
+                template.clone(),
+            )));
+        }
+
+        Self(notifiers)
+    }
+
+    fn send_to_all(&self, send: impl Fn(&dyn Notifier) -> Result<()>) -> Result<()> {
+        // Try every notifier, even if an earlier one fails
+        let failures = self
+            .0
+            .iter()
+            .enumerate()
+            .filter_map(|(i, notifier)| match send(notifier.as_ref()) {
+                Ok(()) => {
+                    metrics::increment(&metrics::NOTIFICATIONS_SENT);
+                    None
+                }
+                Err(e) => Some(format!("notifier {} ({}): {:?}", i + 1, notifier.name(), e)),
+            })
+            .collect::<Vec<_>>();
+
+        if !failures.is_empty() {
+            bail!("Unable to send to {}", failures.join(", "));
+        }
+
+        Ok(())
+    }
+}
+
+impl Notifier for Notifiers {
+    fn name(&self) -> &'static str {
+        "all"
+    }
+
+    fn send(&self, content: &NotifyContent) -> Result<()> {
+        self.send_to_all(|notifier| notifier.send(content))
+    }
+
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()> {
+        self.send_to_all(|notifier| notifier.send_batch(contents))
+    }
+}
+
+/// What a `RecordingNotifier` was asked to send
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SentNotification {
+    pub title: String,
+    pub description: Option<String>,
+    pub mentions: Vec<Mention>,
+}
+
+/// Records everything it's asked to send, optionally failing afterwards. Clones share what's
+/// been sent, so several notifiers can record into the same list.
+#[cfg(test)]
+#[derive(Default, Clone)]
+pub struct RecordingNotifier {
+    sent: std::rc::Rc<std::cell::RefCell<Vec<SentNotification>>>,
+    pub fail: bool,
+}
+
+#[cfg(test)]
+impl RecordingNotifier {
+    /// Everything sent so far, which is then forgotten
+    pub fn take_sent(&self) -> Vec<SentNotification> {
+        std::mem::take(&mut self.sent.borrow_mut())
+    }
+}
+
+#[cfg(test)]
+impl Notifier for RecordingNotifier {
+    fn name(&self) -> &'static str {
+        "recording"
+    }
+
+    fn send(&self, content: &NotifyContent) -> Result<()> {
+        let mut mentions = content.mentions.iter().cloned().collect::<Vec<_>>();
+        mentions.sort();
+
+        self.sent.borrow_mut().push(SentNotification {
+            title: content.title(),
+            description: content.description(),
+            mentions,
+        });
+
+        if self.fail {
+            bail!("Failed to send");
+        }
+        Ok(())
+    }
+
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()> {
+        contents.iter().try_for_each(|content| self.send(content))
+    }
+}
+
+/// Returns whether the notification was sent, rather than held back for quiet hours. A dry run
+/// stands in for sending, so counts as sent.
+pub fn notify(
+    config: &Config,
+    notifier: &dyn Notifier,
+    content: NotifyContent,
+    log: &dyn Logger,
+) -> Result<bool> {
+    if config.dry_run {
+        log.info(&format!(
+            "Dry run, not sending: {}",
+            describe_dry_run(config, &content)
+        ));
+        return Ok(true);
+    }
+
+    if is_quiet(config) {
+        log.info(&format!("Quiet hours, not sending: {}", content.title()));
+        return Ok(false);
+    }
+
+    let result = notifier.send(&content);
+    record_audit(config, std::slice::from_ref(&content), &result, log);
+
+    result.map(|()| true)
+}
+
+/// Send several notifications as one message per notifier, so a round starting doesn't flood
+/// the channel. As with `notify`, returns whether they were sent.
+pub fn notify_batch(
+    config: &Config,
+    notifier: &dyn Notifier,
+    contents: &[NotifyContent],
+    log: &dyn Logger,
+) -> Result<bool> {
+    if config.dry_run {
+        let descriptions = contents
+            .iter()
+            .map(|content| describe_dry_run(config, content))
+            .collect::<Vec<_>>();
+
+        log.info(&format!(
+            "Dry run, not sending batch: {}",
+            descriptions.join("; ")
+        ));
+        return Ok(true);
+    }
+
+    if is_quiet(config) {
+        log.info(&format!(
+            "Quiet hours, not sending {} notifications",
+            contents.len()
+        ));
+        return Ok(false);
+    }
+
+    let result = notifier.send_batch(contents);
+    record_audit(config, contents, &result, log);
+
+    result.map(|()| true)
+}
+
+// Failing to write the audit log shouldn't stop notifications being sent
+fn record_audit(
+    config: &Config,
+    contents: &[NotifyContent],
+    result: &Result<()>,
+    log: &dyn Logger,
+) {
+    let Some(audit_log) = &config.audit_log else {
+        return;
     };
 
-    discord::send_message(
-        &config.notify_webhook,
-        &format!(
-            "[`{}`]({}) `{}` vs. `{}`{}",
-            content.tournament, TCEC_URL, content.white_player, content.black_player, mentions_str
-        ),
-    )
+    if let Err(e) = audit::record(audit_log, contents, result) {
+        log.error(&format!("Unable to write to audit log: {:?}", e));
+    }
+}
+
+fn is_quiet(config: &Config) -> bool {
+    config
+        .quiet_hours
+        .is_some_and(|quiet_hours| quiet_hours.is_quiet_now())
+}
+
+fn describe_dry_run(config: &Config, content: &NotifyContent) -> String {
+    let mut mentions = content.mentions.iter().collect::<Vec<_>>();
+    mentions.sort();
+
+    let mentions = mentions
+        .iter()
+        .map(|m| m.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    let message = match content.template(&config.message_template) {
+        Some(template) => template.render(content, &mentions),
+        None => content.text(|label, url| format!("{label} ({url})")),
+    };
+
+    if mentions.is_empty() {
+        format!("{message} (mentioning nobody)")
+    } else {
+        format!("{message} (mentioning {mentions})")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tcec::TCEC_URL;
+    use crate::tcec_pgn::get_pgn_info;
+
+    fn content(white_elo: Option<u32>, black_elo: Option<u32>) -> NotifyContent {
+        NotifyContent {
+            kind: NotifyKind::Started,
+            white_player: EngineName::new("c4ke 1.1"),
+            black_player: EngineName::new("Minic 3.44"),
+            white_elo,
+            black_elo,
+            tournament: "TCEC Season 29 - Category 1 Playoff".to_string(),
+            round: None,
+            url: TCEC_URL.to_string(),
+            eco: None,
+            opening: Some("Sicilian".to_string()),
+            variation: None,
+            time_control: None,
+            book_length: None,
+            eval: None,
+            followed_side: None,
+            mentions: HashSet::new(),
+            engine_template: None,
+        }
+    }
+
+    #[test]
+    fn test_template_fills_placeholders() {
+        let template =
+            MessageTemplate::new(":chess_pawn: {white} vs {black} in {tournament} {mentions}")
+                .unwrap();
+
+        assert_eq!(
+            template.render(&content(None, None), "<@!123>"),
+            ":chess_pawn: c4ke 1.1 vs Minic 3.44 in TCEC Season 29 - Category 1 Playoff <@!123>"
+        );
+    }
+
+    #[test]
+    fn test_engine_template_is_used_instead_of_template() {
+        let template = Some(MessageTemplate::new("{white} vs {black}").unwrap());
+        let engine_template = MessageTemplate::new(":fire: {black} is playing!").unwrap();
+
+        let content = content(None, None);
+        assert_eq!(content.template(&template), template.as_ref());
+
+        let content = content.with_engine_template(Some(engine_template.clone()));
+        assert_eq!(content.template(&template), Some(&engine_template));
+        assert_eq!(content.template(&None), Some(&engine_template));
+    }
+
+    #[test]
+    fn test_template_rejects_unknown_placeholders() {
+        assert!(MessageTemplate::new("{white} vs {blak}").is_err());
+    }
+
+    #[test]
+    fn test_game_url_uses_site() {
+        let sample_pgn = |site: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Site "{site}"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+
+1. e4 *
+"#
+            )
+        };
+
+        let site_url = Url::parse(TCEC_URL).unwrap();
+        let mirror_url = Url::parse("https://mirror.example.com/").unwrap();
+
+        let game = get_pgn_info(&sample_pgn("https://tcec-chess.com/#season=29"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            game_url(&game, &mirror_url),
+            "https://tcec-chess.com/#season=29"
+        );
+
+        let game = get_pgn_info(&sample_pgn("TCEC")).unwrap().unwrap();
+        assert_eq!(game_url(&game, &site_url), TCEC_URL);
+        assert_eq!(game_url(&game, &mirror_url), "https://mirror.example.com/");
+    }
+
+    #[test]
+    fn test_started_content_describes_the_whole_game() {
+        let game = get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[BlackElo "3436"]
+[ECO "B43"]
+[Opening "Sicilian"]
+[TimeControl "1800+3"]
+[Variation "Kan, 5.Nc3"]
+[WhiteElo "3183"]
+
+1. e4 {book,} c5 {book,} 2. Nf3 {d=32, wv=0.74,} *
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let content = NotifyContent::new(
+            NotifyKind::Started,
+            &game,
+            &Url::parse(TCEC_URL).unwrap(),
+            HashSet::new(),
+        );
+
+        assert_eq!(
+            content.text(|label, _| label.to_string()),
+            "c4ke 1.1 (3183) vs. Minic 3.44 (3436) (B43 Sicilian, Kan, 5.Nc3)\n\
+             Out of book after 2 plies\n\
+             TCEC Season 29 - Category 1 Playoff, Round 2.1 (30+3)"
+        );
+
+        // Anything the PGN doesn't have is left out
+        let mut content = content;
+        content.eco = None;
+        content.variation = None;
+        assert_eq!(
+            content.title(),
+            "c4ke 1.1 (3183) vs. Minic 3.44 (3436) (Sicilian)"
+        );
+        content.opening = None;
+        assert_eq!(content.title(), "c4ke 1.1 (3183) vs. Minic 3.44 (3436)");
+    }
+
+    #[test]
+    fn test_round_is_shown_when_known() {
+        let mut content = content(None, None);
+        let template = MessageTemplate::new("{white} vs {black} {round}").unwrap();
+
+        assert_eq!(content.footer(), "TCEC Season 29 - Category 1 Playoff");
+        assert_eq!(template.render(&content, ""), "c4ke 1.1 vs Minic 3.44 ");
+
+        content.round = Some("2.1".to_string());
+        assert_eq!(
+            content.footer(),
+            "TCEC Season 29 - Category 1 Playoff, Round 2.1"
+        );
+        assert_eq!(
+            template.render(&content, ""),
+            "c4ke 1.1 vs Minic 3.44 Round 2.1"
+        );
+    }
+
+    #[test]
+    fn test_title_includes_elo() {
+        assert_eq!(
+            content(Some(3183), Some(3436)).title(),
+            "c4ke 1.1 (3183) vs. Minic 3.44 (3436) (Sicilian)"
+        );
+    }
+
+    #[test]
+    fn test_title_omits_missing_elo() {
+        assert_eq!(
+            content(None, Some(3436)).title(),
+            "c4ke 1.1 vs. Minic 3.44 (3436) (Sicilian)"
+        );
+    }
+
+    #[test]
+    fn test_started_description_includes_book_length() {
+        let mut content = content(None, None);
+        assert_eq!(content.description(), None);
+
+        content.book_length = Some(12);
+        assert_eq!(
+            content.description().as_deref(),
+            Some("Out of book after 12 plies")
+        );
+    }
+
+    #[test]
+    fn test_mentions_render_users_and_roles() {
+        assert_eq!(Mention::parse("1234").to_string(), "<@!1234>");
+        assert_eq!(Mention::parse("role:5678").to_string(), "<@&5678>");
+        assert!(Mention::parse("role:5678").is_role());
+    }
+
+    #[test]
+    fn test_notifiers_send_to_every_notifier_despite_failures() {
+        let recording = RecordingNotifier::default();
+        let failing = RecordingNotifier {
+            fail: true,
+            ..recording.clone()
+        };
+
+        let notifiers = Notifiers(vec![Box::new(failing), Box::new(recording.clone())]);
+        let error = notifiers.send(&content(None, None)).unwrap_err();
+
+        assert_eq!(recording.take_sent().len(), 2);
+        assert!(format!("{error:?}").contains("notifier 1 (recording)"));
+        assert!(!format!("{error:?}").contains("notifier 2"));
+    }
 }
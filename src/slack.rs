@@ -0,0 +1,96 @@
+use crate::http::ReqwestClient;
+use crate::notify::{MessageTemplate, Notifier, NotifyContent};
+use anyhow::Result;
+use serde_json::json;
+
+pub struct SlackNotifier {
+    client: ReqwestClient,
+    webhook_url: String,
+    template: Option<MessageTemplate>,
+}
+
+impl SlackNotifier {
+    pub fn new(
+        client: ReqwestClient,
+        webhook_url: &str,
+        template: Option<MessageTemplate>,
+    ) -> Self {
+        Self {
+            client,
+            webhook_url: webhook_url.to_string(),
+            template,
+        }
+    }
+
+    // Discord user IDs don't mean anything to Slack, so mentions are dropped
+    fn text(&self, content: &NotifyContent) -> String {
+        match content.template(&self.template) {
+            Some(template) => template.render(content, ""),
+            None => content.text(link),
+        }
+    }
+}
+
+impl Notifier for SlackNotifier {
+    fn name(&self) -> &'static str {
+        "Slack"
+    }
+
+    fn send(&self, content: &NotifyContent) -> Result<()> {
+        send_message(&self.client, &self.webhook_url, &self.text(content))
+    }
+
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()> {
+        let text = contents
+            .iter()
+            .map(|content| self.text(content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        send_message(&self.client, &self.webhook_url, &text)
+    }
+}
+
+fn link(label: &str, url: &str) -> String {
+    format!("<{}|{}>", url, escape(label))
+}
+
+// Slack treats these as control characters in message text
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+pub fn send_message(client: &ReqwestClient, webhook_url: &str, text: &str) -> Result<()> {
+    client
+        .post_json(webhook_url, &json!({ "text": text }))?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn test_send_message_payload() {
+        let server = MockServer::start(vec![MockResponse::new(200, "ok")]);
+
+        send_message(&ReqwestClient::for_tests(), server.url(), "hello").unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].ends_with(r#"{"text":"hello"}"#));
+    }
+
+    #[test]
+    fn test_link_uses_slack_format() {
+        assert_eq!(
+            link("A vs. B <test>", "https://tcec-chess.com/"),
+            "<https://tcec-chess.com/|A vs. B &lt;test&gt;>"
+        );
+    }
+}
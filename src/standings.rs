@@ -0,0 +1,292 @@
+//! Win, draw and loss records for each engine, built up from the games we see finish.
+
+use crate::log::Logger;
+use crate::tcec::{EngineName, StripPatterns};
+use crate::tcec_pgn::{GameResult, Pgn};
+use anyhow::Result;
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+const STANDINGS_FILE: &str = "standings.txt";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Record {
+    pub wins: u32,
+    pub draws: u32,
+    pub losses: u32,
+}
+
+impl Record {
+    // Counted in half points, so draws don't need floats
+    fn half_points(&self) -> u32 {
+        self.wins * 2 + self.draws
+    }
+}
+
+pub struct Standings {
+    records: HashMap<EngineName, Record>,
+
+    // `None` when the standings are only kept in memory
+    path: Option<PathBuf>,
+}
+
+pub type SharedStandings = Arc<Mutex<Standings>>;
+
+impl Standings {
+    /// Standings are kept alongside the state file
+    pub fn load(state_path: &Path, log: &dyn Logger) -> Result<Self> {
+        Self::load_from(&state_path.with_file_name(STANDINGS_FILE), log)
+    }
+
+    pub(crate) fn load_from(path: &Path, log: &dyn Logger) -> Result<Self> {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut records = HashMap::new();
+
+        for line in contents.lines() {
+            match parse_line(line) {
+                Some((engine, record)) => {
+                    records.insert(engine, record);
+                }
+                None => log.warning(&format!(
+                    "Skipping invalid line in standings file: {:?}",
+                    line
+                )),
+            }
+        }
+
+        Ok(Self {
+            records,
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    pub fn in_memory() -> Self {
+        Self {
+            records: HashMap::new(),
+            path: None,
+        }
+    }
+
+    /// Credit a finished game to both players. This should only be called once for each game.
+    pub fn record(&mut self, game: &Pgn, strip_patterns: &StripPatterns) -> Result<()> {
+        let (white, black) = (&game.white_player, &game.black_player);
+
+        match game.result() {
+            GameResult::WhiteWin => {
+                self.entry(white, strip_patterns).wins += 1;
+                self.entry(black, strip_patterns).losses += 1;
+            }
+            GameResult::BlackWin => {
+                self.entry(white, strip_patterns).losses += 1;
+                self.entry(black, strip_patterns).wins += 1;
+            }
+            GameResult::Draw => {
+                self.entry(white, strip_patterns).draws += 1;
+                self.entry(black, strip_patterns).draws += 1;
+            }
+            GameResult::Ongoing => return Ok(()),
+        }
+
+        self.save()
+    }
+
+    // The standings file doesn't know about strip patterns, so an engine's record is found by
+    // comparing names with them rather than by the key it was loaded with
+    fn entry(&mut self, engine: &EngineName, strip_patterns: &StripPatterns) -> &mut Record {
+        let normalized = engine.normalized(strip_patterns);
+        let key = self
+            .records
+            .keys()
+            .find(|recorded| recorded.normalized(strip_patterns) == normalized)
+            .cloned()
+            .unwrap_or_else(|| engine.clone());
+
+        self.records.entry(key).or_default()
+    }
+
+    // Best first, by points and then by name so the order is stable
+    fn sorted(&self) -> Vec<(&EngineName, &Record)> {
+        let mut records = self.records.iter().collect::<Vec<_>>();
+        records.sort_by(|(engine, record), (other_engine, other_record)| {
+            other_record
+                .half_points()
+                .cmp(&record.half_points())
+                .then_with(|| engine.to_string().cmp(&other_engine.to_string()))
+        });
+
+        records
+    }
+
+    pub fn to_json(&self) -> String {
+        let standings = self
+            .sorted()
+            .into_iter()
+            .map(|(engine, record)| {
+                json!({
+                    "engine": engine.to_string(),
+                    "wins": record.wins,
+                    "draws": record.draws,
+                    "losses": record.losses,
+                })
+            })
+            .collect::<Vec<_>>();
+
+        json!(standings).to_string()
+    }
+
+    fn save(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let contents = self
+            .sorted()
+            .into_iter()
+            .map(|(engine, record)| {
+                format!(
+                    "{} {} {} {}\n",
+                    record.wins, record.draws, record.losses, engine
+                )
+            })
+            .collect::<String>();
+
+        std::fs::write(path, contents)?;
+
+        Ok(())
+    }
+}
+
+// Lines are `wins draws losses name`, with the name last as it may contain spaces
+fn parse_line(line: &str) -> Option<(EngineName, Record)> {
+    let mut parts = line.splitn(4, ' ');
+
+    let record = Record {
+        wins: parts.next()?.parse().ok()?,
+        draws: parts.next()?.parse().ok()?,
+        losses: parts.next()?.parse().ok()?,
+    };
+    let engine = parts.next().filter(|engine| !engine.is_empty())?;
+
+    Some((EngineName::new(engine), record))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::StdoutLogger;
+    use crate::tcec_pgn::get_pgn_info;
+
+    fn test_standings_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "tcec-notifier-test-standings-{}-{}.txt",
+            name,
+            std::process::id()
+        ))
+    }
+
+    fn finished_game(white: &str, black: &str, result: &str) -> Pgn {
+        get_pgn_info(&format!(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "{white}"]
+[Black "{black}"]
+[Result "{result}"]
+
+1. e4 {{book, mb=+0+0+0+0+0,}} c5 {{book, mb=+0+0+0+0+0,}}
+{result}
+"#
+        ))
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_credits_the_right_colors() {
+        let path = test_standings_path("colors");
+        let mut standings = Standings::load_from(&path, &StdoutLogger).unwrap();
+
+        standings
+            .record(
+                &finished_game("c4ke 1.1", "Minic 3.44", "0-1"),
+                &StripPatterns::default(),
+            )
+            .unwrap();
+        standings
+            .record(
+                &finished_game("Minic 3.44", "c4ke 1.1", "1/2-1/2"),
+                &StripPatterns::default(),
+            )
+            .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let c4ke = EngineName::new("c4ke 1.1");
+        let minic = EngineName::new("Minic 3.44");
+
+        assert_eq!(
+            standings.records[&c4ke],
+            Record {
+                wins: 0,
+                draws: 1,
+                losses: 1
+            }
+        );
+        assert_eq!(
+            standings.records[&minic],
+            Record {
+                wins: 1,
+                draws: 1,
+                losses: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_standings_persist_across_loads() {
+        let path = test_standings_path("persist");
+        let mut standings = Standings::load_from(&path, &StdoutLogger).unwrap();
+
+        standings
+            .record(
+                &finished_game("Stockfish dev-20251201", "Leela", "1-0"),
+                &StripPatterns::default(),
+            )
+            .unwrap();
+
+        let reloaded = Standings::load_from(&path, &StdoutLogger).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            reloaded.records[&EngineName::new("Stockfish dev-20251201")],
+            Record {
+                wins: 1,
+                draws: 0,
+                losses: 0
+            }
+        );
+        assert_eq!(reloaded.records[&EngineName::new("Leela")].losses, 1);
+    }
+
+    #[test]
+    fn test_record_strips_custom_patterns() {
+        let mut standings = Standings::in_memory();
+        let strip_patterns = StripPatterns::new(&[r" dev-\d+".to_string()]).unwrap();
+
+        for game in [
+            finished_game("Foo dev-1", "Leela", "1-0"),
+            finished_game("Leela", "Foo dev-2", "0-1"),
+        ] {
+            standings.record(&game, &strip_patterns).unwrap();
+        }
+
+        assert_eq!(standings.records.len(), 2);
+        assert_eq!(standings.records[&EngineName::new("Foo dev-1")].wins, 2);
+    }
+}
@@ -1,44 +1,642 @@
+use crate::log::Logger;
+use crate::tcec::{EngineName, StripPatterns};
 use crate::tcec_pgn::Pgn;
 use anyhow::Result;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs::{File, OpenOptions};
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-const STATE_FILE: &str = "state.bin";
+pub const DEFAULT_STATE_PATH: &str = "state.bin";
+const RESULTS_FILE: &str = "results.bin";
+const ENGINES_FILE: &str = "engines.bin";
+const COOLDOWNS_FILE: &str = "cooldowns.bin";
+const MAX_ENTRIES: usize = 10_000;
 
-pub struct SeenGames {
+pub struct SeenGames(HashFile);
+
+impl SeenGames {
+    pub fn load(state_path: &Path, log: &dyn Logger) -> Result<Self> {
+        Ok(Self(HashFile::load_from(state_path, MAX_ENTRIES, log)?))
+    }
+
+    /// Games seen are only remembered until we exit, and nothing is written to disk
+    pub fn in_memory() -> Self {
+        Self(HashFile::in_memory(MAX_ENTRIES))
+    }
+
+    pub fn contains(&self, game: &Pgn) -> bool {
+        self.0.contains(game.as_hash())
+    }
+
+    pub fn add(&mut self, game: &Pgn) -> Result<()> {
+        self.0.insert(game.as_hash())
+    }
+
+    // Pairings are tracked alongside games, for games we've notified about while still in book
+    pub fn contains_pairing(&self, game: &Pgn) -> bool {
+        self.0.contains(game.as_pairing_hash())
+    }
+
+    pub fn add_pairing(&mut self, game: &Pgn) -> Result<()> {
+        self.0.insert(game.as_pairing_hash())
+    }
+
+    // As are openings we've notified about, which are separate from notifying about the game
+    pub fn contains_opening(&self, game: &Pgn) -> bool {
+        self.0.contains(game.as_opening_hash())
+    }
+
+    pub fn add_opening(&mut self, game: &Pgn) -> Result<()> {
+        self.0.insert(game.as_opening_hash())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.0.sync()
+    }
+}
+
+/// The games we've announced a result for. These are kept apart from the games we've seen
+/// start, as seeing a game doesn't mean we've notified about how it ended.
+pub struct NotifiedResults(HashFile);
+
+impl NotifiedResults {
+    pub fn load(state_path: &Path, log: &dyn Logger) -> Result<Self> {
+        Ok(Self(HashFile::load_from(
+            &state_path.with_file_name(RESULTS_FILE),
+            MAX_ENTRIES,
+            log,
+        )?))
+    }
+
+    pub fn in_memory() -> Self {
+        Self(HashFile::in_memory(MAX_ENTRIES))
+    }
+
+    pub fn contains(&self, game: &Pgn) -> bool {
+        self.0.contains(game.as_hash())
+    }
+
+    pub fn add(&mut self, game: &Pgn) -> Result<()> {
+        self.0.insert(game.as_hash())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.0.sync()
+    }
+}
+
+/// The engines we've seen play in each event, so we can tell when one first appears in it.
+/// Engines are compared by their normalized names, so a new version doesn't count as a new engine.
+/// The config's strip patterns are part of that, so they need to be the same each time.
+pub struct SeenEngines(HashFile);
+
+impl SeenEngines {
+    pub fn load(state_path: &Path, log: &dyn Logger) -> Result<Self> {
+        Ok(Self(HashFile::load_from(
+            &state_path.with_file_name(ENGINES_FILE),
+            MAX_ENTRIES,
+            log,
+        )?))
+    }
+
+    pub fn in_memory() -> Self {
+        Self(HashFile::in_memory(MAX_ENTRIES))
+    }
+
+    pub fn contains(
+        &self,
+        engine: &EngineName,
+        event: &str,
+        strip_patterns: &StripPatterns,
+    ) -> bool {
+        self.0.contains(engine_hash(engine, event, strip_patterns))
+    }
+
+    pub fn add(
+        &mut self,
+        engine: &EngineName,
+        event: &str,
+        strip_patterns: &StripPatterns,
+    ) -> Result<()> {
+        self.0.insert(engine_hash(engine, event, strip_patterns))
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        self.0.sync()
+    }
+}
+
+/// When we last notified that each engine had started a game, so engines with a cooldown aren't
+/// notified about too often. Each line of the file is an engine's hash and the time in seconds
+/// since the Unix epoch, with later lines replacing earlier ones.
+pub struct EngineCooldowns {
+    last_notified: HashMap<u64, u64>,
+    file: Option<StateFile>,
+}
+
+impl EngineCooldowns {
+    pub fn load(state_path: &Path, log: &dyn Logger) -> Result<Self> {
+        let (file, contents) = StateFile::open(&state_path.with_file_name(COOLDOWNS_FILE))?;
+
+        let mut last_notified = HashMap::new();
+        for line in contents.lines() {
+            let parsed = line
+                .split_once(' ')
+                .and_then(|(hash, time)| Some((hash.parse().ok()?, time.parse().ok()?)));
+
+            match parsed {
+                Some((hash, time)) => {
+                    last_notified.insert(hash, time);
+                }
+                None => log.warning(&format!(
+                    "Skipping invalid line in cooldowns file: {:?}",
+                    line
+                )),
+            }
+        }
+
+        let mut cooldowns = Self {
+            last_notified,
+            file: Some(file),
+        };
+
+        // Only the latest time for each engine matters, so drop the rest now and then
+        if contents.lines().count() > cooldowns.last_notified.len() {
+            cooldowns.rewrite()?;
+        }
+
+        Ok(cooldowns)
+    }
+
+    pub fn in_memory() -> Self {
+        Self {
+            last_notified: HashMap::new(),
+            file: None,
+        }
+    }
+
+    /// Whether the engine was last notified about less than `cooldown` before `now`
+    pub fn is_cooling_down(
+        &self,
+        engine: &EngineName,
+        strip_patterns: &StripPatterns,
+        cooldown: Duration,
+        now: SystemTime,
+    ) -> bool {
+        let hash = cooldown_hash(engine, strip_patterns);
+        let Some(&last_notified) = self.last_notified.get(&hash) else {
+            return false;
+        };
+
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+        now.saturating_sub(last_notified) < cooldown.as_secs()
+    }
+
+    pub fn record(
+        &mut self,
+        engine: &EngineName,
+        strip_patterns: &StripPatterns,
+        now: SystemTime,
+    ) -> Result<()> {
+        let hash = cooldown_hash(engine, strip_patterns);
+        let now = now.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        self.last_notified.insert(hash, now);
+        if let Some(file) = &mut self.file {
+            file.append(&format!("{} {}", hash, now))?;
+        }
+
+        Ok(())
+    }
+
+    pub fn sync(&self) -> Result<()> {
+        match &self.file {
+            Some(file) => file.sync(),
+            None => Ok(()),
+        }
+    }
+
+    fn rewrite(&mut self) -> Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let contents = self
+            .last_notified
+            .iter()
+            .map(|(hash, time)| format!("{} {}\n", hash, time))
+            .collect::<String>();
+
+        file.replace(&contents)
+    }
+}
+
+fn cooldown_hash(engine: &EngineName, strip_patterns: &StripPatterns) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    engine.normalized(strip_patterns).hash(&mut hasher);
+    hasher.finish()
+}
+
+fn engine_hash(engine: &EngineName, event: &str, strip_patterns: &StripPatterns) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    engine.normalized(strip_patterns).hash(&mut hasher);
+    event.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// A set of hashes backed by a file, which is appended to as hashes are added so the set
+/// survives restarts
+struct HashFile {
     state: HashSet<u64>,
+
+    // Hashes in the order they were seen, so we know which to prune first
+    order: VecDeque<u64>,
+    max_entries: usize,
+
+    // `None` when the hashes are only kept in memory
+    file: Option<StateFile>,
+
+    // Pruned hashes stay in the file until it's compacted, so this can be over `max_entries`
+    lines_in_file: usize,
+}
+
+impl HashFile {
+    fn load_from(path: &Path, max_entries: usize, log: &dyn Logger) -> Result<Self> {
+        let (file, contents) = StateFile::open(path)?;
+
+        let mut hash_file = Self {
+            file: Some(file),
+            lines_in_file: contents.lines().count(),
+            ..Self::in_memory(max_entries)
+        };
+
+        // A hard kill can leave a partially-written line behind, which shouldn't stop us starting
+        let mut has_invalid_lines = false;
+        for line in contents.lines() {
+            match line.trim().parse::<u64>() {
+                Ok(hash) => hash_file.remember(hash),
+                Err(_) => {
+                    log.warning(&format!("Skipping invalid line in state file: {:?}", line));
+                    has_invalid_lines = true;
+                }
+            }
+        }
+
+        // Otherwise the next append would be glued onto the end of the partial line
+        if has_invalid_lines
+            || !(contents.is_empty() || contents.ends_with('\n'))
+            || hash_file.needs_compacting()
+        {
+            hash_file.rewrite()?;
+        }
+
+        Ok(hash_file)
+    }
+
+    fn in_memory(max_entries: usize) -> Self {
+        Self {
+            state: HashSet::new(),
+            order: VecDeque::new(),
+            max_entries,
+            file: None,
+            lines_in_file: 0,
+        }
+    }
+
+    fn contains(&self, hash: u64) -> bool {
+        self.state.contains(&hash)
+    }
+
+    fn insert(&mut self, hash: u64) -> Result<()> {
+        if self.state.contains(&hash) {
+            return Ok(());
+        }
+
+        self.remember(hash);
+
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        file.append(&hash.to_string())?;
+        self.lines_in_file += 1;
+
+        if self.needs_compacting() {
+            self.rewrite()?;
+        }
+
+        Ok(())
+    }
+
+    // Rewriting the whole file on every insert once we're at the limit would be wasteful, so
+    // pruned hashes are left in the file until there's a good number of them
+    fn needs_compacting(&self) -> bool {
+        self.lines_in_file > self.max_entries + self.max_entries / 10
+    }
+
+    // Track a hash in memory, dropping the oldest ones once we're over the limit
+    fn remember(&mut self, hash: u64) {
+        if !self.state.insert(hash) {
+            return;
+        }
+
+        self.order.push_back(hash);
+
+        while self.order.len() > self.max_entries {
+            if let Some(oldest) = self.order.pop_front() {
+                self.state.remove(&oldest);
+            }
+        }
+    }
+
+    fn rewrite(&mut self) -> Result<()> {
+        let Some(file) = &mut self.file else {
+            return Ok(());
+        };
+
+        let contents = self
+            .order
+            .iter()
+            .map(|hash| format!("{}\n", hash))
+            .collect::<String>();
+
+        file.replace(&contents)?;
+        self.lines_in_file = self.order.len();
+
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        match &self.file {
+            Some(file) => file.sync(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A state file, which is appended to line by line and occasionally rewritten in full
+struct StateFile {
+    path: PathBuf,
     file: File,
 }
 
-impl SeenGames {
-    pub fn load() -> Result<Self> {
+impl StateFile {
+    // Along with the file, returns what was already in it
+    fn open(path: &Path) -> Result<(Self, String)> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
         let mut file = OpenOptions::new()
             .create(true)
             .read(true)
             .append(true)
-            .open(STATE_FILE)?;
+            .open(path)?;
 
         let mut contents = String::new();
         _ = file.read_to_string(&mut contents);
 
-        let state = contents
-            .lines()
-            .map(|l| l.parse::<u64>().expect("Bad state file"))
-            .collect();
+        let state_file = Self {
+            path: path.to_path_buf(),
+            file,
+        };
 
-        Ok(Self { state, file })
+        Ok((state_file, contents))
     }
 
-    pub fn contains(&self, game: &Pgn) -> bool {
-        self.state.contains(&game.as_hash())
+    fn append(&mut self, line: &str) -> Result<()> {
+        writeln!(&mut self.file, "{}", line)?;
+        self.file.sync_data()?;
+
+        Ok(())
     }
 
-    pub fn add(&mut self, game: &Pgn) -> Result<()> {
-        self.state.insert(game.as_hash());
+    fn replace(&mut self, contents: &str) -> Result<()> {
+        // Write a new file and swap it in, so the old one is left intact if we're killed midway
+        let temp_path = self.path.with_extension("tmp");
+        let mut temp_file = File::create(&temp_path)?;
+        temp_file.write_all(contents.as_bytes())?;
+        temp_file.sync_all()?;
+        std::fs::rename(&temp_path, &self.path)?;
+
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
 
-        writeln!(&mut self.file, "{}", game.as_hash())?;
+        Ok(())
+    }
+
+    fn sync(&self) -> Result<()> {
+        self.file.sync_all()?;
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::log::{RecordingLogger, StdoutLogger};
+
+    fn test_state_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "tcec-notifier-test-{}-{}.bin",
+            name,
+            std::process::id()
+        ))
+    }
+
+    #[test]
+    fn test_load_skips_invalid_lines() {
+        let path = test_state_path("invalid-lines");
+        std::fs::write(&path, "12345\ngarbage\n").unwrap();
+
+        let log = RecordingLogger::default();
+        let hash_file = HashFile::load_from(&path, MAX_ENTRIES, &log).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(hash_file.state, HashSet::from([12345]));
+        assert_eq!(
+            log.messages("warning"),
+            vec!["Skipping invalid line in state file: \"garbage\""]
+        );
+    }
+
+    #[test]
+    fn test_load_creates_parent_directories() {
+        let dir = std::env::temp_dir().join(format!(
+            "tcec-notifier-test-state-dir-{}",
+            std::process::id()
+        ));
+        let state_path = dir.join("state").join(DEFAULT_STATE_PATH);
+
+        let mut seen_engines = SeenEngines::load(&state_path, &StdoutLogger).unwrap();
+        seen_engines
+            .add(
+                &EngineName::new("Stockfish"),
+                "TCEC Cup 14",
+                &StripPatterns::default(),
+            )
+            .unwrap();
+        let engines_file_exists = state_path.with_file_name(ENGINES_FILE).exists();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert!(engines_file_exists);
+    }
+
+    #[test]
+    fn test_cooldowns_are_per_engine_and_survive_restarts() {
+        let dir = std::env::temp_dir().join(format!(
+            "tcec-notifier-test-cooldowns-{}",
+            std::process::id()
+        ));
+        let state_path = dir.join(DEFAULT_STATE_PATH);
+        let cooldown = Duration::from_secs(30 * 60);
+        let start = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let stockfish = EngineName::new("Stockfish 17");
+        let leela = EngineName::new("Leela");
+        let strip_patterns = StripPatterns::default();
+
+        let mut cooldowns = EngineCooldowns::load(&state_path, &StdoutLogger).unwrap();
+        cooldowns
+            .record(&stockfish, &strip_patterns, start)
+            .unwrap();
+        cooldowns
+            .record(&stockfish, &strip_patterns, start + Duration::from_secs(60))
+            .unwrap();
+
+        let reloaded = EngineCooldowns::load(&state_path, &StdoutLogger).unwrap();
+        let contents = std::fs::read_to_string(state_path.with_file_name(COOLDOWNS_FILE)).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let later = |minutes: u64| start + Duration::from_secs(minutes * 60);
+
+        // A new version of the engine is still the same engine
+        let new_version = EngineName::new("Stockfish 18");
+        let is_cooling_down = |engine, minutes| {
+            reloaded.is_cooling_down(engine, &strip_patterns, cooldown, later(minutes))
+        };
+        assert!(is_cooling_down(&new_version, 30));
+        assert!(!is_cooling_down(&stockfish, 31));
+        assert!(!is_cooling_down(&leela, 1));
+        assert_eq!(contents.lines().count(), 1);
+    }
+
+    #[test]
+    fn test_insert_prunes_oldest_entries() {
+        let path = test_state_path("prune");
+        std::fs::write(&path, "1\n2\n").unwrap();
+
+        let mut hash_file = HashFile::load_from(&path, 2, &StdoutLogger).unwrap();
+        hash_file.insert(3).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(hash_file.state, HashSet::from([2, 3]));
+        assert_eq!(contents, "2\n3\n");
+    }
+
+    #[test]
+    fn test_insert_only_compacts_once_well_over_the_limit() {
+        let path = test_state_path("compact");
+        let mut hash_file = HashFile::load_from(&path, 20, &StdoutLogger).unwrap();
+
+        let mut rewrites = 0;
+        let mut lines_before = 0;
+        for hash in 1..=100 {
+            hash_file.insert(hash).unwrap();
+
+            let lines = std::fs::read_to_string(&path).unwrap().lines().count();
+            if lines < lines_before {
+                rewrites += 1;
+            }
+            lines_before = lines;
+        }
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        // The file is compacted to the newest 20 once it's over 22 lines, so every third insert
+        assert_eq!(rewrites, 26);
+        assert_eq!(hash_file.state, (81..=100).collect());
+        assert_eq!(contents.lines().next(), Some("79"));
+        assert_eq!(contents.lines().count(), 22);
+    }
+
+    #[test]
+    fn test_insert_after_partial_last_line() {
+        let path = test_state_path("partial-line");
+        std::fs::write(&path, "1\n2\n12a").unwrap();
+
+        let mut hash_file = HashFile::load_from(&path, MAX_ENTRIES, &StdoutLogger).unwrap();
+        hash_file.insert(3).unwrap();
+
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(hash_file.state, HashSet::from([1, 2, 3]));
+        assert_eq!(contents, "1\n2\n3\n");
+    }
+
+    #[test]
+    fn test_in_memory_state_forgets_nothing_while_running() {
+        let game = crate::tcec_pgn::get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book,} *
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let mut seen_games = SeenGames::in_memory();
+        assert!(!seen_games.contains(&game));
+
+        seen_games.add(&game).unwrap();
+        assert!(seen_games.contains(&game));
+        assert!(!seen_games.contains_pairing(&game));
+        assert!(seen_games.sync().is_ok());
+
+        let mut hash_file = HashFile::in_memory(2);
+        for hash in 1..=3 {
+            hash_file.insert(hash).unwrap();
+        }
+        assert_eq!(hash_file.state, HashSet::from([2, 3]));
+    }
+
+    #[test]
+    fn test_engine_hash_ignores_versions() {
+        let event = "TCEC Season 29 - Category 1 Playoff";
+        let hash = |name, event, strip_patterns| {
+            engine_hash(&EngineName::new(name), event, strip_patterns)
+        };
+        let no_patterns = StripPatterns::default();
+        let dev_builds = StripPatterns::new(&[r" dev-\d+".to_string()]).unwrap();
+
+        assert_eq!(
+            hash("Stockfish 16", event, &no_patterns),
+            hash("Stockfish 17.1", event, &no_patterns)
+        );
+        assert_ne!(
+            hash("Stockfish 16", event, &no_patterns),
+            hash("Stockfish 16", "TCEC Cup 15", &no_patterns)
+        );
+
+        // Versions the built-in rules don't know about are ignored once they're stripped
+        assert_ne!(
+            hash("Foo dev-1", event, &no_patterns),
+            hash("Foo dev-2", event, &no_patterns)
+        );
+        assert_eq!(
+            hash("Foo dev-1", event, &dev_builds),
+            hash("Foo dev-2", event, &dev_builds)
+        );
+    }
+}
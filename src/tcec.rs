@@ -1,15 +1,22 @@
-use crate::log::Logger;
+use crate::config::Config;
+use crate::http::HttpClient;
 use crate::tcec_pgn;
 use crate::tcec_pgn::Pgn;
-use anyhow::{bail, Result};
+use anyhow::{bail, Context, Result};
 use regex::Regex;
+use reqwest::header::{IF_MODIFIED_SINCE, IF_NONE_MATCH};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
 use std::fmt::Formatter;
 use std::hash::Hasher;
+use unicode_normalization::UnicodeNormalization;
 
-const TCEC_PGN_URL: &str = "https://tcec-chess.com/live.pgn";
+pub const TCEC_PGN_URL: &str = "https://tcec-chess.com/live.pgn";
 pub const TCEC_URL: &str = "https://tcec-chess.com/";
 
-#[derive(Debug, Clone)]
+pub const DEFAULT_FUZZY_THRESHOLD: usize = 2;
+
+#[derive(Debug, Clone, Serialize)]
 pub struct EngineName(String);
 
 impl EngineName {
@@ -17,31 +24,192 @@ impl EngineName {
         Self(name.to_string())
     }
 
-    fn normalize(name: &str) -> String {
-        let mut name = name.to_ascii_lowercase();
+    /// The canonical form of the name used when comparing engines, see [`normalize_engine_name`]
+    pub fn normalized(&self, strip_patterns: &StripPatterns) -> String {
+        normalize_engine_name(&self.0, strip_patterns)
+    }
+
+    pub fn matches(&self, name: &str, options: &MatchOptions) -> bool {
+        let normalized = self.normalized(&options.strip_patterns);
+
+        options
+            .aliases
+            .names_for(name, &options.strip_patterns)
+            .into_iter()
+            .any(|name| match options.mode {
+                MatchMode::Substring => normalized.contains(&name),
+                MatchMode::Exact => normalized == name,
+                MatchMode::Fuzzy => {
+                    normalized.contains(&name)
+                        || strsim::levenshtein(&normalized, &name) <= options.fuzzy_threshold
+                }
+            })
+    }
+}
+
+/// The canonical form of an engine's name, so that different versions of the same engine (and
+/// differences in case or spacing) compare equal, e.g. "Stockfish 16.1" becomes "stockfish".
+/// Any patterns from the config are stripped after the built-in rules.
+pub fn normalize_engine_name(name: &str, strip_patterns: &StripPatterns) -> String {
+    // Copy-pasted names can have non-breaking spaces or other lookalike characters
+    let mut name = name
+        .nfkc()
+        .collect::<String>()
+        .to_ascii_lowercase()
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    // 54101d91 (a git short hash)
+    let commit_hash_regex = Regex::new(r" [0-9a-f]{7,}$").unwrap();
+    name = commit_hash_regex.replace_all(&name, "").trim().to_string();
+
+    // v1.2.3
+    let version_regex = Regex::new(r" v?(\d+)(\.\d+)?(\.\d+)?$").unwrap();
+    name = version_regex.replace_all(&name, "").trim().to_string();
+
+    // 2025a
+    let date_version_regex = Regex::new(r" \d{4}[a-zA-Z]").unwrap();
+    name = date_version_regex.replace_all(&name, "").trim().to_string();
+
+    strip_patterns.strip(&name)
+}
+
+/// Other names an engine is known by, e.g. after being renamed between seasons.
+/// Aliases are bidirectional, so following either name will match both, but they aren't transitive.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Aliases(HashMap<String, HashSet<String>>);
+
+impl Aliases {
+    pub fn new(aliases: &HashMap<String, String>, strip_patterns: &StripPatterns) -> Self {
+        let mut names: HashMap<String, HashSet<String>> = HashMap::new();
+
+        for (alias, name) in aliases {
+            let alias = normalize_engine_name(alias, strip_patterns);
+            let name = normalize_engine_name(name, strip_patterns);
 
-        // v1.2.3
-        let version_regex = Regex::new(r" v?(\d+)(\.\d+)?(\.\d+)?$").unwrap();
-        name = version_regex.replace_all(&name, "").trim().to_string();
+            names.entry(alias.clone()).or_default().insert(name.clone());
+            names.entry(name).or_default().insert(alias);
+        }
+
+        Self(names)
+    }
+
+    // The normalized name, along with the normalized form of any of its aliases
+    fn names_for(&self, name: &str, strip_patterns: &StripPatterns) -> Vec<String> {
+        let name = normalize_engine_name(name, strip_patterns);
+
+        let mut names = vec![name.clone()];
+        if let Some(aliases) = self.0.get(&name) {
+            names.extend(aliases.iter().cloned());
+        }
+
+        names
+    }
+}
+
+/// How a followed name is compared against a player's name, after both have been normalized
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchMode {
+    /// The player's name only needs to contain the followed name, so "Stock" matches "Stockfish"
+    #[default]
+    Substring,
+
+    /// The names must be equal
+    Exact,
+
+    /// As with `Substring`, but also allowing small typos, so "Stokfish" matches "Stockfish"
+    Fuzzy,
+}
+
+/// Additional patterns to strip from engine names, for version schemes the built-in rules don't
+/// cover.
+/// These are applied after the built-in rules wherever names are normalized, so they match
+/// against the lowercased name.
+#[derive(Debug, Default, Clone)]
+pub struct StripPatterns(Vec<Regex>);
+
+impl StripPatterns {
+    pub fn new(patterns: &[String]) -> Result<Self> {
+        let patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).with_context(|| format!("Invalid strip pattern `{pattern}`"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self(patterns))
+    }
 
-        // 2025a
-        let date_version_regex = Regex::new(r" \d{4}[a-zA-Z]").unwrap();
-        name = date_version_regex.replace_all(&name, "").trim().to_string();
+    fn strip(&self, name: &str) -> String {
+        let mut name = name.to_string();
+
+        for pattern in &self.0 {
+            name = pattern.replace_all(&name, "").trim().to_string();
+        }
 
         name
     }
+}
 
-    pub fn matches(&self, name: &str) -> bool {
-        Self::normalize(&self.0).contains(&Self::normalize(name))
+impl PartialEq for StripPatterns {
+    fn eq(&self, other: &Self) -> bool {
+        self.0
+            .iter()
+            .map(Regex::as_str)
+            .eq(other.0.iter().map(Regex::as_str))
     }
 }
 
+/// Settings which control how followed engine names are matched against players
+#[derive(Debug, Clone, PartialEq)]
+pub struct MatchOptions {
+    pub mode: MatchMode,
+    pub aliases: Aliases,
+    pub strip_patterns: StripPatterns,
+
+    /// The maximum edit distance between names for them to match in `MatchMode::Fuzzy`
+    pub fuzzy_threshold: usize,
+}
+
+impl MatchOptions {
+    /// The same options without any typo tolerance, to tell whether a match was only a fuzzy one
+    pub fn without_fuzzy(&self) -> Self {
+        let mode = match self.mode {
+            MatchMode::Fuzzy => MatchMode::Substring,
+            mode => mode,
+        };
+
+        Self {
+            mode,
+            ..self.clone()
+        }
+    }
+}
+
+impl Default for MatchOptions {
+    fn default() -> Self {
+        Self {
+            mode: MatchMode::default(),
+            aliases: Aliases::default(),
+            strip_patterns: StripPatterns::default(),
+            fuzzy_threshold: DEFAULT_FUZZY_THRESHOLD,
+        }
+    }
+}
+
+// Only the built-in rules are used here, as these identify the players within a game. Engines
+// compared across games, e.g. in the state and standings, use `normalized` with the config's
+// strip patterns.
 impl PartialEq for EngineName {
     fn eq(&self, other: &Self) -> bool {
-        Self::normalize(&self.0) == Self::normalize(&other.0)
+        self.normalized(&StripPatterns::default()) == other.normalized(&StripPatterns::default())
     }
 }
 
+impl Eq for EngineName {}
+
 impl std::fmt::Display for EngineName {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
@@ -50,59 +218,314 @@ impl std::fmt::Display for EngineName {
 
 impl std::hash::Hash for EngineName {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        Self::normalize(&self.0).hash(state);
+        self.normalized(&StripPatterns::default()).hash(state);
     }
 }
 
-fn get_current_pgn() -> Result<Pgn> {
-    let client = reqwest::blocking::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .build()?;
+/// Validators from the last fetch of the live PGN, so the server only needs to send it again
+/// once it has changed
+#[derive(Debug, Default)]
+pub struct PgnCache {
+    etag: Option<String>,
+    last_modified: Option<String>,
+}
+
+#[derive(Debug)]
+pub enum CurrentGames {
+    /// The PGN hasn't changed since we last fetched it
+    Unchanged,
+    Games(Vec<Pgn>),
+}
+
+fn get_current_pgn(
+    config: &Config,
+    client: &dyn HttpClient,
+    cache: &mut PgnCache,
+) -> Result<Option<String>> {
+    let pgn_content = match &config.pgn_file {
+        // Re-read on every poll, so the file can be edited while we're running
+        Some(pgn_file) => Some(
+            std::fs::read_to_string(pgn_file)
+                .with_context(|| format!("Unable to read PGN file {}", pgn_file.display()))?,
+        ),
+        None => fetch_current_pgn(client, config.pgn_url.as_str(), cache)?,
+    };
+
+    Ok(pgn_content)
+}
 
-    let response = client.get(TCEC_PGN_URL).send()?.error_for_status()?;
+// Returns `None` if the PGN hasn't changed since it was last fetched
+fn fetch_current_pgn(
+    client: &dyn HttpClient,
+    url: &str,
+    cache: &mut PgnCache,
+) -> Result<Option<String>> {
+    let mut headers = vec![];
 
-    if response.status() != reqwest::StatusCode::OK {
-        bail!("Unexpected server response: {}", response.status());
+    if let Some(etag) = &cache.etag {
+        headers.push((IF_NONE_MATCH, etag.as_str()));
     }
 
-    let pgn_content = response.text()?;
+    if let Some(last_modified) = &cache.last_modified {
+        headers.push((IF_MODIFIED_SINCE, last_modified.as_str()));
+    }
 
-    let pgn_info = tcec_pgn::get_pgn_info(&pgn_content)?;
+    let response = client.get(url, &headers)?;
 
-    Ok(pgn_info)
-}
+    if response.status == reqwest::StatusCode::NOT_MODIFIED.as_u16() {
+        return Ok(None);
+    }
 
-pub fn get_current_game(log: &dyn Logger) -> Result<Option<Pgn>> {
-    let pgn_fetch_result = get_current_pgn();
+    if response.status != reqwest::StatusCode::OK.as_u16() {
+        bail!("Unexpected server response: {}", response.status);
+    }
 
-    let Ok(pgn) = pgn_fetch_result else {
-        let e = pgn_fetch_result.unwrap_err();
+    cache.etag = response.etag;
+    cache.last_modified = response.last_modified;
 
-        log.warning(&format!("Unable to fetch PGN {:?}", e));
+    Ok(Some(response.body))
+}
 
-        return Err(e);
+/// Fetch the games currently being played. This is only the first board unless `all_boards` is
+/// set. Games which are still in book are only returned if `notify_in_book` is set, as otherwise
+/// we don't consider them to have started yet.
+pub fn get_current_games(
+    config: &Config,
+    client: &dyn HttpClient,
+    cache: &mut PgnCache,
+) -> Result<CurrentGames> {
+    let Some(pgn_content) = get_current_pgn(config, client, cache)? else {
+        return Ok(CurrentGames::Unchanged);
     };
 
-    if !pgn.out_of_book() {
-        return Ok(None);
-    }
+    let pgns = if config.all_boards {
+        tcec_pgn::get_all_pgn_info(&pgn_content)
+    } else {
+        // No games are in progress between events
+        tcec_pgn::get_pgn_info(&pgn_content).map(|pgn| pgn.into_iter().collect())
+    };
 
-    Ok(Some(pgn))
+    // The PGN may have been fetched while it was being written. Forget its validators so the
+    // next poll fetches it again, rather than being told it hasn't changed.
+    let pgns = pgns.inspect_err(|_| *cache = PgnCache::default())?;
+
+    Ok(CurrentGames::Games(
+        pgns.into_iter()
+            .filter(|pgn| config.notify_in_book || pgn.out_of_book())
+            .collect(),
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::http::{FakeHttpClient, HttpResponse, ReqwestClient, DEFAULT_USER_AGENT};
+    use crate::mock_server::{MockResponse, MockServer};
+    use std::time::{Duration, Instant};
+
+    const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn normalize(name: &str) -> String {
+        normalize_engine_name(name, &StripPatterns::default())
+    }
+
+    #[test]
+    fn test_fetch_times_out_on_slow_server() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200, "1. e4 *").with_delay(Duration::from_secs(2))
+        ]);
+
+        let started = Instant::now();
+        let client = ReqwestClient::new(Duration::from_millis(100), DEFAULT_USER_AGENT).unwrap();
+        let result = fetch_current_pgn(&client, server.url(), &mut PgnCache::default());
+
+        assert!(result.is_err());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_fetch_sends_cache_validators() {
+        let server = MockServer::start(vec![
+            MockResponse::new(200, "1. e4 *")
+                .with_header("ETag", "\"abc\"")
+                .with_header("Last-Modified", "Tue, 02 Dec 2025 13:20:38 GMT"),
+            MockResponse::new(304, ""),
+        ]);
+
+        let client = ReqwestClient::new(TEST_TIMEOUT, DEFAULT_USER_AGENT).unwrap();
+        let mut cache = PgnCache::default();
+        let first = fetch_current_pgn(&client, server.url(), &mut cache).unwrap();
+        let second = fetch_current_pgn(&client, server.url(), &mut cache).unwrap();
+
+        assert_eq!(first.as_deref(), Some("1. e4 *"));
+        assert_eq!(second, None);
+
+        let requests = server.requests();
+        assert!(requests[0]
+            .to_ascii_lowercase()
+            .contains("user-agent: tcec-notifier/"));
+
+        let conditional_request = requests[1].to_ascii_lowercase();
+        assert!(conditional_request.contains("if-none-match: \"abc\"\r\n"));
+        assert!(
+            conditional_request.contains("if-modified-since: tue, 02 dec 2025 13:20:38 gmt\r\n")
+        );
+    }
+
+    #[test]
+    fn test_fetch_rejects_unexpected_success_status() {
+        let client = FakeHttpClient::new(vec![
+            Ok(HttpResponse {
+                status: 200,
+                body: "1. e4 *".to_string(),
+                etag: Some("\"abc\"".to_string()),
+                ..Default::default()
+            }),
+            Ok(HttpResponse {
+                status: 204,
+                ..Default::default()
+            }),
+        ]);
+
+        let mut cache = PgnCache::default();
+        let first = fetch_current_pgn(&client, TCEC_PGN_URL, &mut cache).unwrap();
+        let second = fetch_current_pgn(&client, TCEC_PGN_URL, &mut cache);
+
+        assert_eq!(first.as_deref(), Some("1. e4 *"));
+        assert!(second.is_err());
+
+        let requests = client.requests();
+        assert_eq!(requests[0].1, vec![]);
+        assert_eq!(requests[1].1, vec![(IF_NONE_MATCH, "\"abc\"".to_string())]);
+    }
 
     #[test]
     fn test_matches_ignores_version() {
-        assert!(EngineName::new("Lunar 2").matches("Lunar"));
-        assert!(EngineName::new("Lunar 2.0").matches("Lunar"));
-        assert!(EngineName::new("Lunar 2.0.1").matches("Lunar"));
+        let options = MatchOptions::default();
+        assert!(EngineName::new("Lunar 2").matches("Lunar", &options));
+        assert!(EngineName::new("Lunar 2.0").matches("Lunar", &options));
+        assert!(EngineName::new("Lunar 2.0.1").matches("Lunar", &options));
     }
 
     #[test]
     fn test_matches_ignores_date_version() {
-        assert!(EngineName::new("Colossus 2025b").matches("Colossus"));
+        let options = MatchOptions::default();
+        assert!(EngineName::new("Colossus 2025b").matches("Colossus", &options));
+    }
+
+    #[test]
+    fn test_normalize_strips_commit_hash() {
+        assert_eq!(normalize("Sirius 54101d91"), "sirius");
+    }
+
+    #[test]
+    fn test_normalize_preserves_non_hex_words() {
+        assert_eq!(normalize("Caissa Galactic"), "caissa galactic");
+        assert_eq!(normalize("Igel cafe"), "igel cafe");
+    }
+
+    #[test]
+    fn test_normalize_collapses_whitespace() {
+        assert_eq!(normalize("Leela  Chess"), "leela chess");
+        assert_eq!(normalize(" Leela Chess 0.31 "), "leela chess");
+
+        let options = MatchOptions {
+            mode: MatchMode::Exact,
+            ..Default::default()
+        };
+        assert!(EngineName::new("Leela Chess").matches("Leela  Chess", &options));
+        assert!(EngineName::new("Leela\u{a0}Chess").matches("Leela Chess", &options));
+    }
+
+    #[test]
+    fn test_exact_match_mode_requires_equal_names() {
+        let options = MatchOptions {
+            mode: MatchMode::Exact,
+            ..Default::default()
+        };
+
+        assert!(EngineName::new("Stockfish 17").matches("Stockfish", &options));
+        assert!(!EngineName::new("Stockfish 17").matches("Stock", &options));
+        assert!(EngineName::new("Stockfish 17").matches("Stock", &MatchOptions::default()));
+    }
+
+    #[test]
+    fn test_matches_strips_custom_patterns() {
+        let options = MatchOptions {
+            mode: MatchMode::Exact,
+            strip_patterns: StripPatterns::new(&[r" dev-\d+".to_string()]).unwrap(),
+            ..Default::default()
+        };
+
+        assert!(EngineName::new("Berserk dev-20250101").matches("Berserk", &options));
+        assert!(!EngineName::new("Berserk dev-20250101").matches(
+            "Berserk",
+            &MatchOptions {
+                mode: MatchMode::Exact,
+                ..Default::default()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_normalize_strips_custom_patterns() {
+        let strip_patterns = StripPatterns::new(&[r" dev-\d+".to_string()]).unwrap();
+
+        assert_eq!(
+            normalize_engine_name("Foo dev-1", &strip_patterns),
+            normalize_engine_name("Foo dev-2", &strip_patterns)
+        );
+        assert_eq!(
+            EngineName::new("Foo dev-1").normalized(&strip_patterns),
+            "foo"
+        );
+        assert_eq!(
+            EngineName::new("Foo dev-1").normalized(&StripPatterns::default()),
+            "foo dev-1"
+        );
+    }
+
+    #[test]
+    fn test_fuzzy_match_mode_allows_typos() {
+        let options = MatchOptions {
+            mode: MatchMode::Fuzzy,
+            ..Default::default()
+        };
+
+        assert!(EngineName::new("Stockfish 17").matches("Stokfish", &options));
+        assert!(!EngineName::new("Stockfish 17").matches("Stokfish", &options.without_fuzzy()));
+    }
+
+    #[test]
+    fn test_fuzzy_match_mode_rejects_distant_names() {
+        let options = MatchOptions {
+            mode: MatchMode::Fuzzy,
+            ..Default::default()
+        };
+
+        assert!(!EngineName::new("Stockfish 17").matches("Leela", &options));
+        assert!(!EngineName::new("Stockfish 17").matches("Starfish", &options));
+    }
+
+    #[test]
+    fn test_invalid_strip_pattern_is_an_error() {
+        assert!(StripPatterns::new(&["(".to_string()]).is_err());
+    }
+
+    #[test]
+    fn test_matches_aliases_in_both_directions() {
+        let options = MatchOptions {
+            aliases: Aliases::new(
+                &HashMap::from([("Stoofvlees".to_string(), "ScorpioNN".to_string())]),
+                &StripPatterns::default(),
+            ),
+            ..Default::default()
+        };
+
+        assert!(EngineName::new("ScorpioNN 3.0.15").matches("Stoofvlees", &options));
+        assert!(EngineName::new("Stoofvlees II a14").matches("ScorpioNN", &options));
+        assert!(
+            !EngineName::new("ScorpioNN 3.0.15").matches("Stoofvlees", &MatchOptions::default())
+        );
     }
 }
@@ -1,26 +1,250 @@
-use crate::tcec::EngineName;
-use anyhow::{bail, Result};
+use crate::tcec::{EngineName, MatchOptions};
+use anyhow::{anyhow, Result};
 use pgn_reader::{BufferedReader, RawComment, RawHeader, SanPlus, Skip, Visitor};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt::Formatter;
 use std::hash::{Hash, Hasher};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
 
 const EVENT_KEY: &str = "Event";
+const SITE_HEADER_KEY: &str = "Site";
 const WHITE_HEADER_KEY: &str = "White";
 const BLACK_HEADER_KEY: &str = "Black";
+const WHITE_ELO_HEADER_KEY: &str = "WhiteElo";
+const BLACK_ELO_HEADER_KEY: &str = "BlackElo";
 const DATE_HEADER_KEY: &str = "Date";
+const ROUND_HEADER_KEY: &str = "Round";
+const RESULT_HEADER_KEY: &str = "Result";
+const ECO_HEADER_KEY: &str = "ECO";
+const OPENING_HEADER_KEY: &str = "Opening";
+const VARIATION_HEADER_KEY: &str = "Variation";
+const TIME_CONTROL_HEADER_KEY: &str = "TimeControl";
+const GAME_START_TIME_HEADER_KEY: &str = "GameStartTime";
+const TERMINATION_HEADER_KEY: &str = "Termination";
 const BOOK_MOVE_COMMENT_PREFIX: &str = "book,";
+const EVAL_COMMENT_KEY: &str = "wv";
+const MATERIAL_BALANCE_COMMENT_KEY: &str = "mb";
+const PV_COMMENT_KEY: &str = "pv";
+const CLOCK_COMMENT_KEY: &str = "tl";
+const TABLEBASE_HITS_COMMENT_KEY: &str = "tb";
+const DEPTH_COMMENT_KEY: &str = "d";
+const SELECTIVE_DEPTH_COMMENT_KEY: &str = "sd";
+const NODES_COMMENT_KEY: &str = "n";
+const FIFTY_MOVE_COMMENT_KEY: &str = "R50";
+
+// Engines occasionally reach a tablebase position deep in their search well before the endgame,
+// so it takes more than a handful of hits to count as being in tablebase territory
+const TABLEBASE_ENDGAME_HITS: u64 = 1000;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PgnMove {
     notation: String,
     in_book: bool,
+    eval: Option<Eval>,
+    material_balance: Option<MaterialBalance>,
+
+    /// The line the engine expected to be played, starting with this move, in SAN
+    pv: Option<Vec<String>>,
+
+    /// The time the side which played this move had left after playing it
+    clock: Option<Duration>,
+
+    /// How many tablebase positions the engine reached while searching for this move
+    tablebase_hits: Option<u64>,
+    search_stats: Option<SearchStats>,
+
+    /// How many moves were left before the game could be drawn by the 50-move rule
+    fifty_move_counter: Option<u32>,
+}
+
+/// How deep and wide the engine searched before playing a move
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct SearchStats {
+    pub depth: u32,
+
+    /// The deepest any line was searched, which is usually well beyond `depth`
+    pub selective_depth: u32,
+    pub nodes: u64,
+}
+
+/// How much more of each type of piece white has than black
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct MaterialBalance {
+    pub pawns: i32,
+    pub knights: i32,
+    pub bishops: i32,
+    pub rooks: i32,
+    pub queens: i32,
+}
+
+impl MaterialBalance {
+    /// The balance in pawns, using the usual 1/3/3/5/9 piece values
+    pub fn points(&self) -> i32 {
+        self.pawns + 3 * self.knights + 3 * self.bishops + 5 * self.rooks + 9 * self.queens
+    }
+
+    /// Who is ahead on material and by how much, if anyone is
+    pub fn describe(&self) -> Option<String> {
+        let points = self.points();
+        let side = if points > 0 { "White" } else { "Black" };
+
+        match points.abs() {
+            0 => None,
+            1 => Some(format!("{side} is up a pawn")),
+            points => Some(format!("{side} is up {points} pawns of material")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum GameResult {
+    WhiteWin,
+    BlackWin,
+    Draw,
+    Ongoing,
 }
 
-#[derive(Debug, Clone)]
+impl GameResult {
+    fn parse(result: &str) -> Self {
+        match result {
+            "1-0" => GameResult::WhiteWin,
+            "0-1" => GameResult::BlackWin,
+            "1/2-1/2" => GameResult::Draw,
+            _ => GameResult::Ongoing,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        *self != GameResult::Ongoing
+    }
+
+    pub fn is_decisive(&self) -> bool {
+        matches!(self, GameResult::WhiteWin | GameResult::BlackWin)
+    }
+}
+
+impl std::fmt::Display for GameResult {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let result = match self {
+            GameResult::WhiteWin => "1-0",
+            GameResult::BlackWin => "0-1",
+            GameResult::Draw => "1/2-1/2",
+            GameResult::Ongoing => "*",
+        };
+
+        write!(f, "{}", result)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TimeControl {
+    pub base: Duration,
+    pub increment: Duration,
+}
+
+impl TimeControl {
+    // TCEC gives time controls in seconds, e.g. `1800+3`
+    fn parse(time_control: &str) -> Option<Self> {
+        let (base, increment) = time_control.trim().split_once('+')?;
+
+        Some(Self {
+            base: Duration::from_secs(base.parse().ok()?),
+            increment: Duration::from_secs(increment.parse().ok()?),
+        })
+    }
+}
+
+// Rendered as minutes+seconds, e.g. `30+3`, which is how time controls are usually written
+impl std::fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let base_secs = self.base.as_secs();
+
+        if base_secs.is_multiple_of(60) {
+            write!(f, "{}+{}", base_secs / 60, self.increment.as_secs())
+        } else {
+            write!(f, "{}s+{}", base_secs, self.increment.as_secs())
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    White,
+    Black,
+}
+
+/// The time each side had left after its latest move
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Clocks {
+    pub white: Option<Duration>,
+    pub black: Option<Duration>,
+}
+
+/// An engine's evaluation of a position, from white's perspective
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum Eval {
+    /// White's advantage in pawns
+    Cp(f32),
+
+    /// A forced mate in this many moves, which is negative when black is the one mating
+    Mate(i32),
+}
+
+impl Eval {
+    /// White's advantage in pawns, where a mate for either side is an infinite advantage
+    pub fn pawns(&self) -> f32 {
+        match self {
+            Eval::Cp(pawns) => *pawns,
+            Eval::Mate(moves) if *moves > 0 => f32::INFINITY,
+            Eval::Mate(_) => f32::NEG_INFINITY,
+        }
+    }
+}
+
+impl std::fmt::Display for Eval {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Eval::Cp(pawns) => write!(f, "{:+.2}", pawns),
+            Eval::Mate(moves) => {
+                let side = if *moves > 0 { "white" } else { "black" };
+                write!(f, "Mate in {} for {}", moves.unsigned_abs(), side)
+            }
+        }
+    }
+}
+
+/// A change in evaluation between two consecutive evaluated moves
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EvalSwing {
+    /// The index into `moves` of the move after which the evaluation changed
+    pub ply: usize,
+    pub swing: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct Pgn {
     pub white_player: EngineName,
     pub black_player: EngineName,
+    pub white_elo: Option<u32>,
+    pub black_elo: Option<u32>,
     pub date: String,
+    pub round: Option<String>,
     pub event: String,
+    pub site: Option<String>,
+    pub eco: Option<String>,
+    pub opening_name: Option<String>,
+    pub variation: Option<String>,
+    pub time_control: Option<TimeControl>,
+
+    /// When TCEC started the game, which tells apart games which are otherwise identical
+    pub start_time: Option<String>,
+
+    /// Why the game ended, e.g. `adjudication` or `time forfeit`
+    pub termination: Option<String>,
+    result: GameResult,
 
     pub moves: Vec<PgnMove>,
 }
@@ -33,21 +257,177 @@ impl Pgn {
         self.moves.iter().take_while(|mv| mv.in_book)
     }
 
+    /// The material balance after the latest move which reported one
+    pub fn material_balance(&self) -> Option<MaterialBalance> {
+        self.moves.iter().rev().find_map(|mv| mv.material_balance)
+    }
+
+    /// The principal variation reported with the last move
+    pub fn latest_pv(&self) -> Option<&[String]> {
+        self.moves.last()?.pv.as_deref()
+    }
+
+    /// The remaining time for each side, from the last of its moves which reported one
+    pub fn clocks(&self) -> Clocks {
+        // White plays the even plies and black the odd ones
+        let latest_clock = |first_ply: usize| {
+            self.moves
+                .iter()
+                .skip(first_ply)
+                .step_by(2)
+                .rev()
+                .find_map(|mv| mv.clock)
+        };
+
+        Clocks {
+            white: latest_clock(0),
+            black: latest_clock(1),
+        }
+    }
+
+    /// The search stats reported with the latest move which had them
+    pub fn search_stats(&self) -> Option<SearchStats> {
+        self.moves.iter().rev().find_map(|mv| mv.search_stats)
+    }
+
+    /// The game as JSON, along with the details worked out from its moves
+    pub fn to_json(&self) -> Value {
+        let mut json = json!(self);
+        json["book_length"] = json!(self.book_length());
+        json["move_count"] = json!(self.move_count());
+
+        json
+    }
+
+    /// How many moves are left before the game can be drawn by the 50-move rule, as of the last
+    /// move. This is `None` if the last move didn't say, e.g. because it was a book move.
+    pub fn fifty_move_counter(&self) -> Option<u32> {
+        self.moves.last()?.fifty_move_counter
+    }
+
+    /// Whether the engine which played the last move was finding tablebase positions in its
+    /// search. This is false if it didn't say.
+    pub fn is_tablebase_endgame(&self) -> bool {
+        self.moves
+            .last()
+            .and_then(|mv| mv.tablebase_hits)
+            .is_some_and(|hits| hits >= TABLEBASE_ENDGAME_HITS)
+    }
+
+    /// The number of half-moves played, including those from book
+    pub fn move_count(&self) -> usize {
+        self.moves.len()
+    }
+
+    /// The number of moves played, counted the way move numbers are, so a move by white alone
+    /// counts as a move
+    pub fn full_move_count(&self) -> usize {
+        self.move_count().div_ceil(2)
+    }
+
+    /// The number of half-moves played from the opening book
+    pub fn book_length(&self) -> usize {
+        self.opening().count()
+    }
+
     /// The game is 'out of book' if any of the moves that were played are not book moves
     pub fn out_of_book(&self) -> bool {
         self.moves.iter().any(|mv| !mv.in_book)
     }
 
-    pub fn has_player(&self, player: &str) -> bool {
-        self.white_player_is(player) || self.black_player_is(player)
+    /// The evaluation (from white's perspective) reported for the last non-book move
+    pub fn latest_eval(&self) -> Option<Eval> {
+        self.moves
+            .iter()
+            .rev()
+            .find(|mv| !mv.in_book)
+            .and_then(|mv| mv.eval)
+    }
+
+    /// The largest absolute change in evaluation between consecutive non-book moves. Mates have
+    /// no size in pawns, so moves with one are skipped.
+    pub fn max_eval_swing(&self) -> Option<EvalSwing> {
+        let evals = self
+            .moves
+            .iter()
+            .enumerate()
+            .filter(|(_, mv)| !mv.in_book)
+            .filter_map(|(ply, mv)| match mv.eval? {
+                Eval::Cp(pawns) => Some((ply, pawns)),
+                Eval::Mate(_) => None,
+            })
+            .collect::<Vec<_>>();
+
+        evals
+            .windows(2)
+            .map(|pair| EvalSwing {
+                ply: pair[1].0,
+                swing: (pair[1].1 - pair[0].1).abs(),
+            })
+            .max_by(|a, b| a.swing.total_cmp(&b.swing))
     }
 
-    fn white_player_is(&self, player: &str) -> bool {
-        self.white_player.matches(player)
+    pub fn result(&self) -> GameResult {
+        self.result
     }
 
-    fn black_player_is(&self, player: &str) -> bool {
-        self.black_player.matches(player)
+    /// Whether the game has a result without having been played out at the board, e.g. because
+    /// an engine forfeited or was given a bye
+    pub fn is_forfeit(&self) -> bool {
+        // A `time forfeit` is a game lost on time, which was played
+        let forfeit_termination = self.termination.as_deref().is_some_and(|termination| {
+            let termination = termination.to_ascii_lowercase();
+            termination == "abandoned"
+                || (termination.contains("forfeit") && !termination.contains("time"))
+        });
+
+        self.result.is_finished() && (!self.out_of_book() || forfeit_termination)
+    }
+
+    /// Whether the game was played more than `max_age` before `now`. The date is only a day, so
+    /// the age is counted from the end of that day in UTC, so a game which started just before
+    /// midnight isn't stale just after it.
+    pub fn is_stale(&self, max_age: Duration, now: SystemTime) -> bool {
+        let Some(days) = parse_date(&self.date) else {
+            return false;
+        };
+
+        let end_of_day = UNIX_EPOCH + Duration::from_secs((days + 1) * SECONDS_PER_DAY);
+        now.duration_since(end_of_day)
+            .is_ok_and(|age| age > max_age)
+    }
+
+    pub fn has_player(&self, player: &str, options: &MatchOptions) -> bool {
+        self.white_player_is(player, options) || self.black_player_is(player, options)
+    }
+
+    /// Whether the game is between the two players, with either of them playing white
+    pub fn is_between(&self, player: &str, other: &str, options: &MatchOptions) -> bool {
+        (self.white_player_is(player, options) && self.black_player_is(other, options))
+            || (self.white_player_is(other, options) && self.black_player_is(player, options))
+    }
+
+    /// Whether the game is playing an opening, given either as an ECO code like `B43` or as
+    /// part of the opening's name
+    pub fn has_opening(&self, opening: &str) -> bool {
+        if is_eco_code(opening) {
+            return self
+                .eco
+                .as_ref()
+                .is_some_and(|eco| eco.eq_ignore_ascii_case(opening));
+        }
+
+        self.opening_name
+            .as_ref()
+            .is_some_and(|name| name.to_lowercase().contains(&opening.to_lowercase()))
+    }
+
+    fn white_player_is(&self, player: &str, options: &MatchOptions) -> bool {
+        self.white_player.matches(player, options)
+    }
+
+    fn black_player_is(&self, player: &str, options: &MatchOptions) -> bool {
+        self.black_player.matches(player, options)
     }
 
     pub fn as_hash(&self) -> u64 {
@@ -55,17 +435,78 @@ impl Pgn {
         self.hash(&mut hasher);
         hasher.finish()
     }
+
+    /// A hash of the headers identifying the pairing, leaving out the book.
+    /// Unlike `as_hash`, this doesn't change as book moves are played, so it can identify a game
+    /// which hasn't left book yet.
+    pub fn as_pairing_hash(&self) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        self.white_player.hash(&mut hasher);
+        self.black_player.hash(&mut hasher);
+        self.date.hash(&mut hasher);
+        self.round.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// A hash for having notified about this game's opening, kept apart from the game itself so
+    /// the two notifications don't stop each other
+    pub fn as_opening_hash(&self) -> u64 {
+        let mut hasher = std::hash::DefaultHasher::new();
+        ECO_HEADER_KEY.hash(&mut hasher);
+        self.as_pairing_hash().hash(&mut hasher);
+        hasher.finish()
+    }
 }
 
-// The hash of a TCEC PGN is the hash of the players, the date, and the book.
+/// The number of days since the Unix epoch of a PGN date like `2025.12.02`. Unknown parts of
+/// the date are written as `??`, which gives `None`, as do years too far off to be real.
+fn parse_date(date: &str) -> Option<u64> {
+    let mut parts = date.split('.').map(|part| part.parse::<u64>().ok());
+    let (year, month, day) = (parts.next()??, parts.next()??, parts.next()??);
+
+    if parts.next().is_some()
+        || !(1970..=9999).contains(&year)
+        || !(1..=12).contains(&month)
+        || !(1..=31).contains(&day)
+    {
+        return None;
+    }
+
+    // Howard Hinnant's days_from_civil, with years starting in March so leap days come last
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year / 400;
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+// ECO codes are a letter from A to E followed by two digits
+fn is_eco_code(opening: &str) -> bool {
+    let mut chars = opening.chars();
+
+    matches!(chars.next(), Some('A'..='E' | 'a'..='e'))
+        && chars.clone().count() == 2
+        && chars.all(|c| c.is_ascii_digit())
+}
+
+// The hash of a TCEC PGN is the hash of the players, the date, the round and the book.
 // That is to say, we consider games equivalent if they are played by the same players
-// on the same day, with the same opening book.
-// FIXME: This doesn't account for replays.
+// on the same day, with the same opening book. Including the round means that a replay
+// of a game is treated as a new game. When TCEC tells us when the game started, that's
+// included too, as it's the most reliable way of telling two games apart.
 impl Hash for Pgn {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.white_player.hash(state);
         self.black_player.hash(state);
         self.date.hash(state);
+        self.round.hash(state);
+
+        // Only hashed when present, so games without it hash the same as they always have
+        if let Some(start_time) = &self.start_time {
+            start_time.hash(state);
+        }
 
         for mv in self.opening() {
             mv.notation.hash(state);
@@ -84,8 +525,19 @@ impl Eq for Pgn {}
 struct PgnInfoBuilder {
     pub white_player: Option<String>,
     pub black_player: Option<String>,
+    pub white_elo: Option<u32>,
+    pub black_elo: Option<u32>,
     pub date: Option<String>,
+    pub round: Option<String>,
     pub event: Option<String>,
+    pub site: Option<String>,
+    pub eco: Option<String>,
+    pub opening_name: Option<String>,
+    pub variation: Option<String>,
+    pub time_control: Option<String>,
+    pub start_time: Option<String>,
+    pub termination: Option<String>,
+    pub result: Option<String>,
 
     pub moves: Vec<PgnMove>,
 
@@ -98,8 +550,19 @@ impl PgnInfoBuilder {
         Self {
             white_player: None,
             black_player: None,
+            white_elo: None,
+            black_elo: None,
             date: None,
+            round: None,
             event: None,
+            site: None,
+            eco: None,
+            opening_name: None,
+            variation: None,
+            time_control: None,
+            start_time: None,
+            termination: None,
+            result: None,
             moves: vec![],
 
             last_san: None,
@@ -115,12 +578,119 @@ impl PgnInfoBuilder {
         self.moves.push(PgnMove {
             notation: san.to_owned(),
             in_book: is_book_move,
+            eval: parse_eval(comment),
+            material_balance: parse_material_balance(comment),
+            pv: parse_pv(comment),
+            clock: parse_clock(comment),
+            tablebase_hits: parse_tablebase_hits(comment),
+            search_stats: parse_search_stats(comment),
+            fifty_move_counter: parse_fifty_move_counter(comment),
         });
     }
 }
 
+// Engine move comments are a list of `key=value` tokens, e.g. `d=32, sd=32, mt=96132, ...`
+fn comment_value<'a>(comment: &'a str, key: &str) -> Option<&'a str> {
+    comment
+        .split(',')
+        .filter_map(|token| token.trim().split_once('='))
+        .find(|(k, _)| *k == key)
+        .map(|(_, value)| value)
+}
+
+// Mates are given directly, e.g. `M12` or `-#12`, and every other number is an evaluation in pawns,
+// however large. Anything else which isn't a plain number (e.g. `null`) is ignored.
+fn parse_eval(comment: &str) -> Option<Eval> {
+    let eval = comment_value(comment, EVAL_COMMENT_KEY)?.trim();
+
+    let (sign, unsigned) = match eval.strip_prefix('-') {
+        Some(unsigned) => (-1, unsigned),
+        None => (1, eval.strip_prefix('+').unwrap_or(eval)),
+    };
+
+    if let Some(moves) = unsigned.strip_prefix(['M', '#']) {
+        let moves = moves.parse::<i32>().ok().filter(|moves| *moves > 0)?;
+        return Some(Eval::Mate(sign * moves));
+    }
+
+    let pawns = eval.parse::<f32>().ok().filter(|pawns| pawns.is_finite())?;
+    Some(Eval::Cp(pawns))
+}
+
+// The PV is a space-separated list of moves in SAN, e.g. `Bb7 O-O Be7`
+fn parse_pv(comment: &str) -> Option<Vec<String>> {
+    let pv = comment_value(comment, PV_COMMENT_KEY)?
+        .split_whitespace()
+        .map(|mv| mv.to_string())
+        .collect::<Vec<_>>();
+
+    (!pv.is_empty()).then_some(pv)
+}
+
+// The remaining time is given in milliseconds
+fn parse_clock(comment: &str) -> Option<Duration> {
+    comment_value(comment, CLOCK_COMMENT_KEY)?
+        .parse::<u64>()
+        .ok()
+        .map(Duration::from_millis)
+}
+
+// Moves which didn't probe the tablebases say `tb=null`
+fn parse_tablebase_hits(comment: &str) -> Option<u64> {
+    comment_value(comment, TABLEBASE_HITS_COMMENT_KEY)?
+        .parse::<u64>()
+        .ok()
+}
+
+// Book moves weren't searched, so have none of these
+fn parse_search_stats(comment: &str) -> Option<SearchStats> {
+    Some(SearchStats {
+        depth: comment_value(comment, DEPTH_COMMENT_KEY)?.parse().ok()?,
+        selective_depth: comment_value(comment, SELECTIVE_DEPTH_COMMENT_KEY)?
+            .parse()
+            .ok()?,
+        nodes: comment_value(comment, NODES_COMMENT_KEY)?.parse().ok()?,
+    })
+}
+
+// TCEC counts down from 50, resetting after every capture or pawn move
+fn parse_fifty_move_counter(comment: &str) -> Option<u32> {
+    comment_value(comment, FIFTY_MOVE_COMMENT_KEY)?
+        .parse::<u32>()
+        .ok()
+}
+
+// The balance is a signed delta for each piece type, e.g. `+0-1+0+0+0`
+fn parse_material_balance(comment: &str) -> Option<MaterialBalance> {
+    let balance = comment_value(comment, MATERIAL_BALANCE_COMMENT_KEY)?.trim();
+
+    let mut deltas = vec![];
+    let mut start = 0;
+    for (i, c) in balance.char_indices().skip(1) {
+        if c == '+' || c == '-' {
+            deltas.push(balance[start..i].parse::<i32>().ok()?);
+            start = i;
+        }
+    }
+    deltas.push(balance[start..].parse::<i32>().ok()?);
+
+    let [pawns, knights, bishops, rooks, queens] = deltas[..] else {
+        return None;
+    };
+
+    Some(MaterialBalance {
+        pawns,
+        knights,
+        bishops,
+        rooks,
+        queens,
+    })
+}
+
 impl Visitor for PgnInfoBuilder {
-    type Result = Pgn;
+    // A PGN fetched while it's being written can be cut off, so missing headers are an error
+    // rather than a panic
+    type Result = Result<Pgn>;
 
     fn header(&mut self, key: &[u8], value: RawHeader<'_>) {
         let key = String::from_utf8_lossy(key);
@@ -130,6 +700,10 @@ impl Visitor for PgnInfoBuilder {
             self.event = Some(value.to_string());
         }
 
+        if key == SITE_HEADER_KEY {
+            self.site = Some(value.to_string());
+        }
+
         if key == WHITE_HEADER_KEY {
             self.white_player = Some(value.to_string());
         }
@@ -138,14 +712,55 @@ impl Visitor for PgnInfoBuilder {
             self.black_player = Some(value.to_string());
         }
 
+        // Ratings which aren't plain numbers (e.g. `?` when unknown) are ignored
+        if key == WHITE_ELO_HEADER_KEY {
+            self.white_elo = value.trim().parse().ok();
+        }
+
+        if key == BLACK_ELO_HEADER_KEY {
+            self.black_elo = value.trim().parse().ok();
+        }
+
         if key == DATE_HEADER_KEY {
             self.date = Some(value.to_string());
         }
+
+        if key == ROUND_HEADER_KEY {
+            self.round = Some(value.to_string());
+        }
+
+        if key == ECO_HEADER_KEY {
+            self.eco = Some(value.to_string());
+        }
+
+        if key == OPENING_HEADER_KEY {
+            self.opening_name = Some(value.to_string());
+        }
+
+        if key == VARIATION_HEADER_KEY {
+            self.variation = Some(value.to_string());
+        }
+
+        if key == TIME_CONTROL_HEADER_KEY {
+            self.time_control = Some(value.to_string());
+        }
+
+        if key == GAME_START_TIME_HEADER_KEY {
+            self.start_time = Some(value.to_string());
+        }
+
+        if key == TERMINATION_HEADER_KEY {
+            self.termination = Some(value.to_string());
+        }
+
+        if key == RESULT_HEADER_KEY {
+            self.result = Some(value.to_string());
+        }
     }
 
     fn san(&mut self, san: SanPlus) {
         if let Some(last_san) = self.last_san.clone() {
-            self.add_move(&last_san, &self.last_comment.clone().unwrap_or(String::new()))
+            self.add_move(&last_san, &self.last_comment.clone().unwrap_or_default())
         }
 
         self.last_comment = None;
@@ -164,34 +779,53 @@ impl Visitor for PgnInfoBuilder {
     fn end_game(&mut self) -> Self::Result {
         // Handle the last move we saw
         if let Some(last_san) = self.last_san.clone() {
-            self.add_move(&last_san, &self.last_comment.clone().unwrap_or(String::new()))
+            self.add_move(&last_san, &self.last_comment.clone().unwrap_or_default())
         }
 
-        assert_ne!(self.white_player, None);
-        assert_ne!(self.black_player, None);
-        assert_ne!(self.date, None);
-        assert_ne!(self.event, None);
+        let required_header = |value: &Option<String>, key: &str| {
+            value
+                .clone()
+                .ok_or_else(|| anyhow!("PGN is missing the {key} header"))
+        };
 
-        Pgn {
-            white_player: EngineName::new(&self.white_player.clone().unwrap()),
-            black_player: EngineName::new(&self.black_player.clone().unwrap()),
-            date: self.date.clone().unwrap(),
-            event: self.event.clone().unwrap(),
+        Ok(Pgn {
+            white_player: EngineName::new(&required_header(&self.white_player, WHITE_HEADER_KEY)?),
+            black_player: EngineName::new(&required_header(&self.black_player, BLACK_HEADER_KEY)?),
+            white_elo: self.white_elo,
+            black_elo: self.black_elo,
+            date: required_header(&self.date, DATE_HEADER_KEY)?,
+            round: self.round.clone(),
+            event: required_header(&self.event, EVENT_KEY)?,
+            site: self.site.clone(),
+            eco: self.eco.clone(),
+            opening_name: self.opening_name.clone(),
+            variation: self.variation.clone(),
+            time_control: self.time_control.as_deref().and_then(TimeControl::parse),
+            start_time: self.start_time.clone(),
+            termination: self.termination.clone(),
+            result: GameResult::parse(self.result.as_deref().unwrap_or_default()),
             moves: self.moves.clone(),
-        }
+        })
     }
 }
 
-pub fn get_pgn_info(pgn: &str) -> Result<Pgn> {
+/// Parse the first game in a PGN. The PGN is empty between events, which isn't an error.
+pub fn get_pgn_info(pgn: &str) -> Result<Option<Pgn>> {
     let mut reader = BufferedReader::new_cursor(pgn);
 
-    let pgn_info = reader.read_game(&mut PgnInfoBuilder::new())?;
+    reader.read_game(&mut PgnInfoBuilder::new())?.transpose()
+}
+
+/// Parse every game in a PGN containing several concatenated games, e.g. one per board
+pub fn get_all_pgn_info(pgn: &str) -> Result<Vec<Pgn>> {
+    let mut reader = BufferedReader::new_cursor(pgn);
 
-    let Some(pgn_info) = pgn_info else {
-        bail!("Empty PGN")
-    };
+    let mut pgn_infos = vec![];
+    while let Some(pgn_info) = reader.read_game(&mut PgnInfoBuilder::new())? {
+        pgn_infos.push(pgn_info?);
+    }
 
-    Ok(pgn_info)
+    Ok(pgn_infos)
 }
 
 #[cfg(test)]
@@ -248,13 +882,451 @@ Qe7 {d=35, sd=55, pd=Qxd6, mt=41546, tl=712742, s=232587351, n=9656562004, pv=Qe
 *
 "#;
 
-        let pgn_info = get_pgn_info(sample_pgn).unwrap();
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
 
-        assert!(pgn_info.white_player.matches("c4ke"));
-        assert!(pgn_info.black_player.matches("Minic"));
+        assert!(pgn_info
+            .white_player
+            .matches("c4ke", &MatchOptions::default()));
+        assert!(pgn_info
+            .black_player
+            .matches("Minic", &MatchOptions::default()));
+        assert_eq!(pgn_info.white_elo, Some(3183));
+        assert_eq!(pgn_info.black_elo, Some(3436));
         assert_eq!(pgn_info.date, "2025.12.02");
         assert_eq!(pgn_info.event, "TCEC Season 29 - Category 1 Playoff");
-        assert!(pgn_info.out_of_book())
+        assert_eq!(pgn_info.round.as_deref(), Some("2.1"));
+        assert_eq!(pgn_info.site.as_deref(), Some("https://tcec-chess.com"));
+        assert_eq!(pgn_info.eco.as_deref(), Some("B43"));
+        assert_eq!(pgn_info.opening_name.as_deref(), Some("Sicilian"));
+        assert_eq!(pgn_info.variation.as_deref(), Some("Kan, 5.Nc3"));
+        assert_eq!(pgn_info.result(), GameResult::Ongoing);
+        assert_eq!(pgn_info.latest_eval(), Some(Eval::Cp(0.76)));
+        assert_eq!(
+            pgn_info.time_control.map(|tc| tc.to_string()).as_deref(),
+            Some("30+3")
+        );
+        assert!(pgn_info.out_of_book());
+        assert_eq!(pgn_info.book_length(), 12);
+        assert_eq!(
+            pgn_info.moves[31].material_balance,
+            Some(MaterialBalance {
+                bishops: -1,
+                ..MaterialBalance::default()
+            })
+        );
+        assert_eq!(
+            pgn_info.material_balance(),
+            Some(MaterialBalance::default())
+        );
+        assert_eq!(pgn_info.latest_pv().unwrap()[..3], ["Qe7", "Qc7", "d6"]);
+        assert_eq!(
+            pgn_info.clocks(),
+            Clocks {
+                white: Some(Duration::from_millis(912605)),
+                black: Some(Duration::from_millis(712742)),
+            }
+        );
+        assert!(pgn_info.is_tablebase_endgame());
+        assert_eq!(pgn_info.moves[0].search_stats, None);
+        assert_eq!(
+            pgn_info.moves[15].search_stats,
+            Some(SearchStats {
+                depth: 35,
+                selective_depth: 53,
+                nodes: 29601378978,
+            })
+        );
+        assert_eq!(
+            pgn_info.search_stats(),
+            Some(SearchStats {
+                depth: 35,
+                selective_depth: 55,
+                nodes: 9656562004,
+            })
+        );
+        assert_eq!(pgn_info.moves[0].fifty_move_counter, None);
+        assert_eq!(pgn_info.moves[29].fifty_move_counter, Some(48));
+        assert_eq!(pgn_info.moves[31].fifty_move_counter, Some(50));
+        assert_eq!(pgn_info.fifty_move_counter(), Some(49));
+        assert_eq!(pgn_info.move_count(), 34);
+        assert_eq!(pgn_info.full_move_count(), 17);
+    }
+
+    #[test]
+    fn test_pgn_to_json() {
+        let pgn_info = get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "1-0"]
+
+1. e4 {book,} c5 {book,} 2. Nf3 {d=32, sd=40, n=1000, wv=0.74,} 1-0
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let json = pgn_info.to_json();
+        assert_eq!(json["white_player"], "c4ke 1.1");
+        assert_eq!(json["result"], "WhiteWin");
+        assert_eq!(json["book_length"], 2);
+        assert_eq!(json["move_count"], 3);
+        assert_eq!(json["moves"][2]["notation"], "Nf3");
+        assert_eq!(json["moves"][2]["eval"]["Cp"], 0.74f32);
+        assert_eq!(json["moves"][2]["search_stats"]["selective_depth"], 40);
+    }
+
+    #[test]
+    fn test_pgn_parsing_multiple_games() {
+        let sample_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book, mb=+0+0+0+0+0,} c5 {d=32, wv=0.30,}
+*
+
+[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.2"]
+[White "Minic 3.44"]
+[Black "c4ke 1.1"]
+[Result "*"]
+
+1. e4 {book, mb=+0+0+0+0+0,} c5 {d=32, wv=0.30,}
+*
+"#;
+
+        let pgn_infos = get_all_pgn_info(sample_pgn).unwrap();
+
+        assert_eq!(pgn_infos.len(), 2);
+        assert_eq!(pgn_infos[0].round.as_deref(), Some("2.1"));
+        assert_eq!(pgn_infos[1].round.as_deref(), Some("2.2"));
+        assert_eq!(
+            get_pgn_info(sample_pgn).unwrap().unwrap().as_hash(),
+            pgn_infos[0].as_hash()
+        );
+    }
+
+    #[test]
+    fn test_time_control_parsing() {
+        assert_eq!(
+            TimeControl::parse("1800+3"),
+            Some(TimeControl {
+                base: Duration::from_secs(1800),
+                increment: Duration::from_secs(3),
+            })
+        );
+        assert_eq!(TimeControl::parse("90+1").unwrap().to_string(), "90s+1");
+        assert_eq!(TimeControl::parse("40/7200:3600"), None);
+        assert_eq!(TimeControl::parse(""), None);
+    }
+
+    #[test]
+    fn test_replays_in_different_rounds_hash_differently() {
+        let sample_pgn = |round: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "{round}"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {{book, mb=+0+0+0+0+0,}} c5 {{book, mb=+0+0+0+0+0,}}
+2. Nf3 {{d=32, sd=32, mt=96132, tl=1706868, s=0, n=0, pv=Nf3, tb=null, h=0.0, ph=0.0, wv=0.74, R50=49, Rd=-9, Rr=-1000, mb=+0+0+0+0+0,}}
+*
+"#
+            )
+        };
+
+        let game = get_pgn_info(&sample_pgn("2.1")).unwrap().unwrap();
+        let replay = get_pgn_info(&sample_pgn("2.2")).unwrap().unwrap();
+
+        assert_eq!(game.round.as_deref(), Some("2.1"));
+        assert_ne!(game.as_hash(), replay.as_hash());
+        assert_eq!(
+            game.as_hash(),
+            get_pgn_info(&sample_pgn("2.1")).unwrap().unwrap().as_hash()
+        );
+    }
+
+    #[test]
+    fn test_parse_date() {
+        assert_eq!(parse_date("1970.01.01"), Some(0));
+        assert_eq!(parse_date("2000.03.01"), Some(11017));
+        assert_eq!(parse_date("2025.12.02"), Some(20424));
+        assert_eq!(parse_date("2025.??.??"), None);
+        assert_eq!(parse_date("2025.13.02"), None);
+        assert_eq!(parse_date("9999.12.31"), Some(2932896));
+        assert_eq!(parse_date("10000.01.01"), None);
+        assert_eq!(parse_date("18446744073709551615.01.01"), None);
+    }
+
+    #[test]
+    fn test_stale_games_are_counted_from_the_end_of_their_day() {
+        let game = get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book, mb=+0+0+0+0+0,} *
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        let max_age = Duration::from_secs(2 * SECONDS_PER_DAY);
+        let end_of_day = UNIX_EPOCH + Duration::from_secs(20425 * SECONDS_PER_DAY);
+
+        // Just after midnight, a game from the day before is as current as it gets
+        assert!(!game.is_stale(max_age, end_of_day + Duration::from_secs(60)));
+        assert!(!game.is_stale(max_age, end_of_day - Duration::from_secs(60)));
+        assert!(!game.is_stale(max_age, end_of_day + max_age));
+        assert!(game.is_stale(max_age, end_of_day + max_age + Duration::from_secs(60)));
+    }
+
+    #[test]
+    fn test_empty_pgn_has_no_game() {
+        assert!(get_pgn_info("").unwrap().is_none());
+        assert!(get_pgn_info("\n\n").unwrap().is_none());
+        assert!(get_all_pgn_info("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_pgn_missing_headers_is_an_error() {
+        let error = get_pgn_info("1. e4 {book,} c5 {book,} 2. Nf3 *").unwrap_err();
+        assert_eq!(error.to_string(), "PGN is missing the White header");
+
+        // The next game was cut off while the PGN was being written
+        let truncated_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book,} *
+
+[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Whi"#;
+        assert!(get_all_pgn_info(truncated_pgn).is_err());
+    }
+
+    #[test]
+    fn test_is_between_ignores_colors() {
+        let game = get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book, mb=+0+0+0+0+0,} c5 {book, mb=+0+0+0+0+0,}
+*
+"#,
+        )
+        .unwrap()
+        .unwrap();
+        let options = MatchOptions::default();
+
+        assert!(game.is_between("c4ke", "Minic", &options));
+        assert!(game.is_between("Minic", "c4ke", &options));
+        assert!(!game.is_between("c4ke", "Stockfish", &options));
+        assert!(!game.is_between("c4ke", "c4ke", &options));
+    }
+
+    #[test]
+    fn test_games_with_different_start_times_hash_differently() {
+        let sample_pgn = |start_time: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+[GameStartTime "{start_time}"]
+
+1. e4 {{book, mb=+0+0+0+0+0,}} c5 {{book, mb=+0+0+0+0+0,}}
+2. Nf3 {{d=32, sd=32, mt=96132, tl=1706868, s=0, n=0, pv=Nf3, tb=null, h=0.0, ph=0.0, wv=0.74, R50=49, Rd=-9, Rr=-1000, mb=+0+0+0+0+0,}}
+*
+"#
+            )
+        };
+
+        let game = get_pgn_info(&sample_pgn("2025-12-02T13:20:38.758 UTC"))
+            .unwrap()
+            .unwrap();
+        let rematch = get_pgn_info(&sample_pgn("2025-12-02T16:34:14.733 UTC"))
+            .unwrap()
+            .unwrap();
+
+        assert_eq!(
+            game.start_time.as_deref(),
+            Some("2025-12-02T13:20:38.758 UTC")
+        );
+        assert_ne!(game.as_hash(), rematch.as_hash());
+    }
+
+    #[test]
+    fn test_max_eval_swing_ignores_book_moves() {
+        let sample_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book, wv=5.00,} c5 {d=32, wv=0.30,} 2. Nf3 {d=32, wv=0.50,} d6 {d=32, wv=-1.70,}
+3. d4 {d=32, wv=-1.20,}
+*
+"#;
+
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
+        let swing = pgn_info.max_eval_swing().unwrap();
+
+        assert_eq!(swing.ply, 3);
+        assert!((swing.swing - 2.2).abs() < 0.001);
+    }
+
+    #[test]
+    fn test_tablebase_endgame_uses_last_move() {
+        let sample_pgn = |last_comment: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {{d=32, tb=24526,}} c5 {{{last_comment}}}
+*
+"#
+            )
+        };
+
+        let is_tablebase_endgame = |last_comment: &str| {
+            get_pgn_info(&sample_pgn(last_comment))
+                .unwrap()
+                .unwrap()
+                .is_tablebase_endgame()
+        };
+
+        assert!(is_tablebase_endgame("d=35, tb=19595, h=99.7,"));
+        assert!(!is_tablebase_endgame("d=33, tb=1, h=36.6,"));
+        assert!(!is_tablebase_endgame("d=34, tb=null, h=0.0,"));
+        assert!(!is_tablebase_endgame("d=34, h=0.0,"));
+    }
+
+    #[test]
+    fn test_max_eval_swing_needs_two_evals() {
+        let sample_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {book, mb=+0+0+0+0+0,} c5 {d=32, wv=0.30,}
+*
+"#;
+
+        assert_eq!(
+            get_pgn_info(sample_pgn).unwrap().unwrap().max_eval_swing(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_pairing_hash_is_stable_while_in_book() {
+        let sample_pgn = |moves: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+{moves}
+*
+"#
+            )
+        };
+
+        let in_book = get_pgn_info(&sample_pgn("1. e4 {book, mb=+0+0+0+0+0,}"))
+            .unwrap()
+            .unwrap();
+        let out_of_book = get_pgn_info(&sample_pgn(
+            "1. e4 {book, mb=+0+0+0+0+0,} c5 {book, mb=+0+0+0+0+0,} 2. Nf3 {d=32, wv=0.74,}",
+        ))
+        .unwrap()
+        .unwrap();
+
+        assert!(!in_book.out_of_book());
+        assert!(out_of_book.out_of_book());
+        assert_ne!(in_book.as_hash(), out_of_book.as_hash());
+        assert_eq!(in_book.as_pairing_hash(), out_of_book.as_pairing_hash());
+    }
+
+    #[test]
+    fn test_pgn_parsing_finished_game_result() {
+        let sample_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Site "https://tcec-chess.com"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "0-1"]
+
+1. e4 {book, mb=+0+0+0+0+0,} c5 {book, mb=+0+0+0+0+0,}
+2. Nf3 {d=32, sd=32, mt=96132, tl=1706868, s=0, n=0, pv=Nf3, tb=null, h=0.0, ph=0.0, wv=0.74, R50=49, Rd=-9, Rr=-1000, mb=+0+0+0+0+0,}
+0-1
+"#;
+
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
+        assert_eq!(pgn_info.result(), GameResult::BlackWin);
+        assert!(pgn_info.result().is_finished());
+        assert!(!pgn_info.is_forfeit());
+    }
+
+    #[test]
+    fn test_forfeits_are_detected() {
+        let sample_pgn = |result: &str, termination: &str, moves: &str| {
+            format!(
+                r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "{result}"]
+[Termination "{termination}"]
+
+{moves} {result}
+"#
+            )
+        };
+        let is_forfeit = |result: &str, termination: &str, moves: &str| {
+            get_pgn_info(&sample_pgn(result, termination, moves))
+                .unwrap()
+                .unwrap()
+                .is_forfeit()
+        };
+
+        let played = "1. e4 {book,} c5 {d=32, wv=0.74,}";
+        let book_only = "1. e4 {book,} c5 {book,}";
+
+        assert!(is_forfeit("1-0", "normal", ""));
+        assert!(is_forfeit("1-0", "normal", book_only));
+        assert!(is_forfeit("0-1", "forfeit", played));
+        assert!(is_forfeit("0-1", "abandoned", played));
+        assert!(!is_forfeit("0-1", "time forfeit", played));
+        assert!(!is_forfeit("1-0", "adjudication", played));
+        assert!(!is_forfeit("*", "unterminated", book_only));
     }
 
     #[test]
@@ -284,8 +1356,45 @@ Qe7 {d=35, sd=55, pd=Qxd6, mt=41546, tl=712742, s=232587351, n=9656562004, pv=Qe
 *
 "#;
 
-        let pgn_info = get_pgn_info(sample_pgn).unwrap();
-        assert!(!pgn_info.out_of_book())
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
+        assert!(!pgn_info.out_of_book());
+        assert_eq!(pgn_info.latest_eval(), None);
+    }
+
+    #[test]
+    fn test_has_opening_by_eco_or_name() {
+        let sample_pgn = r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Site "https://tcec-chess.com"]
+[Date "2025.12.02"]
+[Round "2.1"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+[BlackElo "3436"]
+[ECO "B43"]
+[GameStartTime "2025-12-02T13:20:38.758 UTC"]
+[Opening "Sicilian"]
+[Termination "unterminated"]
+[TimeControl "1800+3"]
+[Variation "Kan, 5.Nc3"]
+[WhiteElo "3183"]
+
+{WhiteEngineOptions: Protocol=uci; Threads=256; Hash=262144;, BlackEngineOptions: Protocol=uci; Threads=512; Hash=256000; PawnHash=2048; NNUEFile=embedded; CommandLineOptions=-uci -syzygyPath /home/syzygy7;}
+1. e4 {book, mb=+0+0+0+0+0,} c5 {book, mb=+0+0+0+0+0,}
+2. Nf3 {book, mb=+0+0+0+0+0,} e6 {book, mb=+0+0+0+0+0,}
+3. d4 {book, mb=+0+0+0+0+0,} cxd4 {book, mb=-1+0+0+0+0,}
+4. Nxd4 {book, mb=+0+0+0+0+0,} a6 {book, mb=+0+0+0+0+0,}
+5. Nc3 {book, mb=+0+0+0+0+0,} Qc7 {book, mb=+0+0+0+0+0,}
+*
+"#;
+
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
+
+        assert!(pgn_info.has_opening("B43"));
+        assert!(pgn_info.has_opening("b43"));
+        assert!(pgn_info.has_opening("sicilian"));
+        assert!(!pgn_info.has_opening("B06"));
+        assert!(!pgn_info.has_opening("French"));
     }
 
     #[test]
@@ -434,7 +1543,136 @@ Re6 {d=36, sd=88, mt=12000, tl=209492, s=242013811, n=2893033104, pv=Re6 Qh8+ Kf
 
 "#;
 
-        let pgn_info = get_pgn_info(sample_pgn).unwrap();
-        assert!(pgn_info.out_of_book())
+        let pgn_info = get_pgn_info(sample_pgn).unwrap().unwrap();
+        assert!(pgn_info.out_of_book());
+        assert_eq!(pgn_info.latest_eval(), Some(Eval::Cp(6.41)));
+        assert_eq!(pgn_info.book_length(), 10);
+        assert_eq!(pgn_info.move_count(), 129);
+        assert_eq!(pgn_info.full_move_count(), 65);
+    }
+
+    #[test]
+    fn test_book_length_without_book_moves() {
+        let pgn_info = get_pgn_info(
+            r#"[Event "TCEC Season 29 - Category 1 Playoff"]
+[Date "2025.12.02"]
+[White "c4ke 1.1"]
+[Black "Minic 3.44"]
+[Result "*"]
+
+1. e4 {d=32, sd=32, mt=96132, tl=1706868, s=0, n=0, pv=e4, tb=null, h=0.0, ph=0.0, wv=0.25,}
+*
+"#,
+        )
+        .unwrap()
+        .unwrap();
+
+        assert!(pgn_info.out_of_book());
+        assert_eq!(pgn_info.book_length(), 0);
+        assert_eq!(pgn_info.move_count(), 1);
+        assert_eq!(pgn_info.full_move_count(), 1);
+    }
+
+    #[test]
+    fn test_parse_material_balance() {
+        assert_eq!(
+            parse_material_balance("d=35, wv=0.92, mb=+0-1+0+0+0,"),
+            Some(MaterialBalance {
+                knights: -1,
+                ..MaterialBalance::default()
+            })
+        );
+        assert_eq!(
+            parse_material_balance("book, mb=+2+0+0-1+1,"),
+            Some(MaterialBalance {
+                pawns: 2,
+                knights: 0,
+                bishops: 0,
+                rooks: -1,
+                queens: 1
+            })
+        );
+        assert_eq!(parse_material_balance("d=32, wv=0.74,"), None);
+        assert_eq!(parse_material_balance("mb=+0+0+0,"), None);
+    }
+
+    #[test]
+    fn test_describe_material_balance() {
+        let exchange = MaterialBalance {
+            bishops: 1,
+            rooks: -1,
+            ..MaterialBalance::default()
+        };
+
+        assert_eq!(exchange.points(), -2);
+        assert_eq!(
+            exchange.describe().as_deref(),
+            Some("Black is up 2 pawns of material")
+        );
+        assert_eq!(MaterialBalance::default().describe(), None);
+    }
+
+    #[test]
+    fn test_parse_pv() {
+        assert_eq!(
+            parse_pv("d=32, pv=O-O e8=Q+ Nxe5#, R50=49,"),
+            Some(vec![
+                "O-O".to_string(),
+                "e8=Q+".to_string(),
+                "Nxe5#".to_string()
+            ])
+        );
+        assert_eq!(parse_pv("d=32, pv=, R50=49,"), None);
+        assert_eq!(parse_pv("book, mb=+0+0+0+0+0,"), None);
+    }
+
+    #[test]
+    fn test_parse_clock() {
+        assert_eq!(
+            parse_clock("d=32, mt=96132, tl=1706868, s=0,"),
+            Some(Duration::from_millis(1706868))
+        );
+        assert_eq!(parse_clock("d=32, tl=null, s=0,"), None);
+        assert_eq!(parse_clock("book, mb=+0+0+0+0+0,"), None);
+    }
+
+    #[test]
+    fn test_parse_eval_skips_non_numeric_values() {
+        assert_eq!(parse_eval("d=32, wv=0.74, R50=49,"), Some(Eval::Cp(0.74)));
+        assert_eq!(parse_eval("d=32, wv=-1.25, R50=49,"), Some(Eval::Cp(-1.25)));
+        assert_eq!(parse_eval("d=32, wv=null, R50=49,"), None);
+        assert_eq!(parse_eval("d=32, wv=M, R50=49,"), None);
+        assert_eq!(parse_eval("d=32, wv=M0, R50=49,"), None);
+        assert_eq!(parse_eval("book, mb=+0+0+0+0+0,"), None);
+    }
+
+    #[test]
+    fn test_parse_eval_mates() {
+        assert_eq!(parse_eval("d=32, wv=M12, R50=49,"), Some(Eval::Mate(12)));
+        assert_eq!(parse_eval("d=32, wv=+M5, R50=49,"), Some(Eval::Mate(5)));
+        assert_eq!(parse_eval("d=32, wv=-M5, R50=49,"), Some(Eval::Mate(-5)));
+        assert_eq!(parse_eval("d=32, wv=#3, R50=49,"), Some(Eval::Mate(3)));
+
+        // Scores near what some engines use for a mate are still just big advantages
+        assert_eq!(
+            parse_eval("d=32, wv=150.00, R50=49,"),
+            Some(Eval::Cp(150.0))
+        );
+        assert_eq!(
+            parse_eval("d=32, wv=317.54, R50=49,"),
+            Some(Eval::Cp(317.54))
+        );
+        assert_eq!(
+            parse_eval("d=32, wv=-320.00, R50=49,"),
+            Some(Eval::Cp(-320.0))
+        );
+        assert_eq!(
+            parse_eval("d=32, wv=320.00, R50=49,"),
+            Some(Eval::Cp(320.0))
+        );
+
+        assert_eq!(Eval::Mate(5).to_string(), "Mate in 5 for white");
+        assert_eq!(Eval::Mate(-3).to_string(), "Mate in 3 for black");
+        assert_eq!(Eval::Cp(0.5).to_string(), "+0.50");
     }
 }
@@ -0,0 +1,138 @@
+use crate::config::TelegramConfig;
+use crate::http::ReqwestClient;
+use crate::notify::{MessageTemplate, Notifier, NotifyContent};
+use anyhow::Result;
+use serde_json::json;
+
+const TELEGRAM_API_URL: &str = "https://api.telegram.org/";
+
+pub struct TelegramNotifier {
+    client: ReqwestClient,
+    bot_token: String,
+    chat_id: String,
+    template: Option<MessageTemplate>,
+}
+
+impl TelegramNotifier {
+    pub fn new(
+        client: ReqwestClient,
+        config: &TelegramConfig,
+        template: Option<MessageTemplate>,
+    ) -> Self {
+        Self {
+            client,
+            bot_token: config.bot_token.clone(),
+            chat_id: config.chat_id.clone(),
+            template,
+        }
+    }
+
+    // Discord user IDs don't mean anything to Telegram, so mentions are dropped
+    fn text(&self, content: &NotifyContent) -> String {
+        match content.template(&self.template) {
+            Some(template) => template.render(content, ""),
+            None => content.text(link),
+        }
+    }
+}
+
+impl Notifier for TelegramNotifier {
+    fn name(&self) -> &'static str {
+        "Telegram"
+    }
+
+    fn send(&self, content: &NotifyContent) -> Result<()> {
+        send_message(
+            &self.client,
+            &self.bot_token,
+            &self.chat_id,
+            &self.text(content),
+        )
+    }
+
+    fn send_batch(&self, contents: &[NotifyContent]) -> Result<()> {
+        let text = contents
+            .iter()
+            .map(|content| self.text(content))
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        send_message(&self.client, &self.bot_token, &self.chat_id, &text)
+    }
+}
+
+// Messages are sent as plain text, so the URL goes on its own line where it will be linkified
+fn link(label: &str, url: &str) -> String {
+    format!("{label}\n{url}")
+}
+
+pub fn send_message(
+    client: &ReqwestClient,
+    bot_token: &str,
+    chat_id: &str,
+    text: &str,
+) -> Result<()> {
+    call_api(client, TELEGRAM_API_URL, bot_token, chat_id, text)
+}
+
+fn call_api(
+    client: &ReqwestClient,
+    api_url: &str,
+    bot_token: &str,
+    chat_id: &str,
+    text: &str,
+) -> Result<()> {
+    let url = format!("{api_url}bot{bot_token}/sendMessage");
+
+    client
+        .post_json(
+            &url,
+            &json!({
+                "chat_id": chat_id,
+                "text": text
+            }),
+        )?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_server::{MockResponse, MockServer};
+
+    #[test]
+    fn test_send_message_posts_to_bot_api() {
+        let server = MockServer::start(vec![MockResponse::new(200, r#"{"ok": true}"#)]);
+
+        call_api(
+            &ReqwestClient::for_tests(),
+            server.url(),
+            "123:abc",
+            "-10042",
+            "hello",
+        )
+        .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert!(requests[0].starts_with("POST /bot123:abc/sendMessage "));
+        assert!(requests[0].contains(r#""chat_id":"-10042""#));
+        assert!(requests[0].contains(r#""text":"hello""#));
+    }
+
+    #[test]
+    fn test_send_message_fails_on_error_status() {
+        let server = MockServer::start(vec![MockResponse::new(401, r#"{"ok": false}"#)]);
+
+        assert!(call_api(
+            &ReqwestClient::for_tests(),
+            server.url(),
+            "123:abc",
+            "-10042",
+            "hello"
+        )
+        .is_err());
+    }
+}